@@ -1,57 +1,121 @@
-use std::path::PathBuf;
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
 
-use log::{error, info};
-use reqwest::header;
+use chrono::{DateTime, Duration, FixedOffset, Local, NaiveDateTime};
+use log::{debug, error, info};
+use reqwest::{header, Url};
 use reqwest_middleware::RequestBuilder;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
 
 use crate::{
     config::Config,
     fanbox::{Creator, FollowingCreator, Post, PostListItem, SupportingCreator},
+    storage::PART_PREFIX,
 };
 
-use super::ArchiveClient;
+use super::{recorder::Recorder, ArchiveClient};
+
+/// Time given to pages fetched outside of fanbox
+const PAGE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+/// Bytes read of pages fetched for link previews
+const MAX_PAGE_SIZE: usize = 512 * 1024;
 
 pub type APIPost = Post;
 pub type APIListCreatorPost = Vec<PostListItem>;
 pub type APIListSupportingCreator = Vec<SupportingCreator>;
 pub type APIListFollowingCreator = Vec<FollowingCreator>;
 pub type APIListCreatorPaginate = Vec<String>;
+pub type APICreator = FollowingCreator;
 
 #[derive(Debug, Clone)]
 pub struct FanboxClient {
     inner: ArchiveClient,
     session: String,
+    pixiv_session: Option<String>,
+    user_agent: String,
     overwrite: bool,
+    since: Option<DateTime<Local>>,
+    max_file_size: Option<u64>,
+    tmp_dir: PathBuf,
+    recorder: Option<Recorder>,
 }
 
 impl FanboxClient {
     pub fn new(config: &Config) -> Self {
         let inner = ArchiveClient::new(config);
         let session = config.session();
+        let pixiv_session = config.pixiv_session();
+        let user_agent = config.user_agent().to_string();
         let overwrite = config.overwrite();
+        let since = config.since();
+        let max_file_size = config.max_file_size();
+        let tmp_dir = config.tmp_dir();
+        let recorder = Recorder::new(config);
         Self {
             inner,
             session,
+            pixiv_session,
+            user_agent,
             overwrite,
+            since,
+            max_file_size,
+            tmp_dir,
+            recorder,
         }
     }
 
-    fn wrap_request(&self, builder: RequestBuilder) -> RequestBuilder {
-        const USER_AGENT: &str =
-            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/126.0.0.0 Safari/537.36 Edg/126.0.0.0";
-        builder
-            .header(header::COOKIE, &self.session)
+    fn wrap_request(&self, builder: RequestBuilder, cookie: Option<&str>) -> RequestBuilder {
+        let builder = builder
             .header(header::ORIGIN, "https://www.fanbox.cc")
-            .header(header::USER_AGENT, USER_AGENT)
+            .header(header::USER_AGENT, &self.user_agent);
+        match cookie {
+            Some(cookie) => builder.header(header::COOKIE, cookie),
+            None => builder,
+        }
+    }
+
+    /// Pick the cookie for a url by host, keeping each session on its own hosts
+    fn cookie_for(&self, url: &str) -> Option<&str> {
+        let host = Url::parse(url).ok()?.host_str()?.to_string();
+        if is_pixiv_host(&host) {
+            self.pixiv_session.as_deref()
+        } else if is_host_of(&host, "fanbox.cc") {
+            Some(&self.session)
+        } else {
+            None
+        }
     }
 
     pub async fn fetch<T: DeserializeOwned>(&self, url: &str) -> Result<T, FanboxAPIResponseError> {
-        let (client, _semaphore) = self.inner.client().await;
-        let request = client.get(url);
-        let request = self.wrap_request(request);
-        let response = request.send().await.expect("Failed to send request");
-        let response = response.bytes().await.expect("Failed to get response body");
+        let response = match &self.recorder {
+            Some(recorder) if recorder.replaying() => {
+                recorder
+                    .replay_api(url)
+                    .await
+                    .map_err(|e| FanboxAPIResponseError {
+                        error: e.to_string(),
+                    })?
+            }
+            _ => {
+                let (client, _semaphore) = self.inner.client().await;
+                let request = client.get(url);
+                let request = self.wrap_request(request, Some(&self.session));
+                let response = request.send().await.map_err(|e| FanboxAPIResponseError {
+                    error: format!("Failed to send request: {}", e),
+                })?;
+                let response = response.bytes().await.map_err(|e| FanboxAPIResponseError {
+                    error: format!("Failed to get response body: {}", e),
+                })?;
+                response.to_vec()
+            }
+        };
+
+        if let Some(recorder) = self.recorder.as_ref().filter(|r| r.recording()) {
+            recorder.record_api(url, &response).await;
+        }
 
         match serde_json::from_slice::<FanboxAPIResponse<T>>(&response) {
             Ok(value) => Ok(value.body),
@@ -65,38 +129,71 @@ impl FanboxClient {
                         }
                         Err(response)
                     }
-                    Err(_) => panic!("{:?}\n{}", error, String::from_utf8_lossy(&response)),
+                    Err(_) => {
+                        debug!("{}", String::from_utf8_lossy(&response));
+                        Err(FanboxAPIResponseError {
+                            error: format!("Unexpected response: {}", error),
+                        })
+                    }
                 }
             }
         }
     }
 
-    pub async fn download(&self, url: &str, path: PathBuf) -> Result<(), reqwest::Error> {
+    /// Download a file, returning the number of bytes written and their sha256 if it was fetched
+    pub async fn download(
+        &self,
+        url: &str,
+        path: PathBuf,
+    ) -> Result<(u64, Option<String>), Box<dyn std::error::Error + Send + Sync>> {
         if !self.overwrite && path.exists() {
             info!("Download was skip ({})", path.display());
-            return Ok(());
+            return Ok((0, None));
+        }
+
+        if let Some(recorder) = self.recorder.as_ref().filter(|r| r.replaying()) {
+            info!("Replaying {} to {}", url, path.display());
+            return Ok((recorder.replay_file(url, &path).await?, None));
         }
 
         let (client, _semaphore) = self.inner.client().await;
         let request = client.get(url);
-        let request = self.wrap_request(request);
-        let response = request.send().await.expect("Failed to send request");
+        let request = self.wrap_request(request, self.cookie_for(url));
+        let response = request.send().await?.error_for_status()?;
+
+        if let (Some(size), Some(max_file_size)) = (response.content_length(), self.max_file_size) {
+            if size > max_file_size {
+                return Err(FileTooLarge { size }.into());
+            }
+        }
 
         info!("Downloading {} to {}", url, path.display());
-        let mut file = tokio::fs::File::create(path).await.unwrap();
-        self.inner.download(response, &mut file).await?;
+        // write into the temp dir, so an interrupted download never looks complete
+        let part = self.part_path();
+        let mut file = tokio::fs::File::create(&part).await?;
+        let (bytes, sha256) = match self.inner.download(response, &mut file).await {
+            Ok(downloaded) => downloaded,
+            Err(e) => {
+                drop(file);
+                tokio::fs::remove_file(&part).await.ok();
+                return Err(e);
+            }
+        };
+        file.flush().await?;
+        drop(file);
+        move_file(&part, &path).await?;
 
-        Ok(())
+        if let Some(recorder) = self.recorder.as_ref().filter(|r| r.recording()) {
+            recorder.record_file(url, &path).await;
+        }
+        Ok((bytes, Some(sha256)))
     }
 
     pub async fn get_supporting_creators(
         &self,
     ) -> Result<APIListSupportingCreator, Box<dyn std::error::Error>> {
         let url = "https://api.fanbox.cc/plan.listSupporting";
-        let list: APIListSupportingCreator = self
-            .fetch(url)
-            .await
-            .expect("Failed to get supporting authors");
+        let list: APIListSupportingCreator = self.fetch(url).await?;
         Ok(list)
     }
 
@@ -104,13 +201,18 @@ impl FanboxClient {
         &self,
     ) -> Result<APIListFollowingCreator, Box<dyn std::error::Error>> {
         let url = "https://api.fanbox.cc/creator.listFollowing";
-        let list: APIListFollowingCreator = self
-            .fetch(url)
-            .await
-            .expect("Failed to get following authors");
+        let list: APIListFollowingCreator = self.fetch(url).await?;
         Ok(list)
     }
 
+    pub async fn get_creator(
+        &self,
+        creator_id: &str,
+    ) -> Result<APICreator, FanboxAPIResponseError> {
+        let url = format!("https://api.fanbox.cc/creator.get?creatorId={}", creator_id);
+        self.fetch(&url).await
+    }
+
     pub async fn get_posts(
         &self,
         creator: &Creator,
@@ -119,37 +221,173 @@ impl FanboxClient {
             "https://api.fanbox.cc/post.paginateCreator?creatorId={}",
             creator.id()
         );
-        let urls: APIListCreatorPaginate = self.fetch(&url).await.expect("Failed to get post list");
+        let mut urls: APIListCreatorPaginate = self.fetch(&url).await?;
+
+        if let Some(since) = self.since {
+            let total = urls.len();
+            urls.retain(|url| !page_before(url, since));
+            debug!("Skip {} pages before {}", total - urls.len(), since);
+        }
 
         let mut tasks = Vec::new();
         for url in urls {
             let client = self.clone();
-            let future = async move {
-                client
-                    .fetch::<APIListCreatorPost>(&url)
-                    .await
-                    .expect("Failed to get post")
-            };
+            let future = async move { client.fetch::<APIListCreatorPost>(&url).await };
             tasks.push(tokio::spawn(future));
         }
 
+        // a missing page would make the post list look shorter than it is
         let mut posts = Vec::new();
         for task in tasks {
-            posts.extend(task.await?.into_iter());
+            posts.extend(task.await??);
         }
 
         Ok(posts)
     }
 
-    pub async fn get_post(&self, post_id: String) -> Result<APIPost, Box<dyn std::error::Error>> {
+    pub async fn get_post(&self, post_id: String) -> Result<APIPost, FanboxAPIResponseError> {
         let url = format!("https://api.fanbox.cc/post.info?postId={}", post_id);
-        let post: APIPost = self.fetch(&url).await.expect("Failed to get post");
-        Ok(post)
+        self.fetch(&url).await
+    }
+
+    /// Get a HTML page outside of fanbox, returning its final url and the start of its body
+    ///
+    /// No cookie is sent, and the request gives up quickly as it is only nice to have.
+    pub async fn fetch_page(
+        &self,
+        url: &str,
+    ) -> Result<(String, String), Box<dyn std::error::Error + Send + Sync>> {
+        if self.recorder.as_ref().is_some_and(|r| r.replaying()) {
+            return Err("not available while replaying".into());
+        }
+
+        let request = self
+            .inner
+            .client
+            .get(url)
+            .header(header::USER_AGENT, &self.user_agent)
+            .timeout(PAGE_TIMEOUT);
+        let mut response = request.send().await?.error_for_status()?;
+        let is_html = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.contains("html"));
+        if !is_html {
+            return Err("not a HTML page".into());
+        }
+
+        let url = response.url().to_string();
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            body.extend_from_slice(&chunk);
+            // the head is all what is needed
+            if body.len() >= MAX_PAGE_SIZE {
+                break;
+            }
+        }
+        Ok((url, String::from_utf8_lossy(&body).into_owned()))
+    }
+
+    /// Get a JSON document outside of fanbox, `None` if it doesn't exist (anymore)
+    pub async fn fetch_json<T: DeserializeOwned>(
+        &self,
+        url: &str,
+    ) -> Result<Option<T>, Box<dyn std::error::Error + Send + Sync>> {
+        if self.recorder.as_ref().is_some_and(|r| r.replaying()) {
+            return Err("not available while replaying".into());
+        }
+
+        let request = self
+            .inner
+            .client
+            .get(url)
+            .header(header::USER_AGENT, &self.user_agent)
+            .timeout(PAGE_TIMEOUT);
+        let response = request.send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let body = response.error_for_status()?.bytes().await?;
+        Ok(Some(serde_json::from_slice(&body)?))
     }
 
     pub fn overwrite(&self) -> bool {
         self.overwrite
     }
+
+    /// Get a unique temporary path for a download, the PID keeps runs sharing a temp dir apart
+    /// example: `.tmp/fanbox-archive-1234-42.part`
+    fn part_path(&self) -> PathBuf {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        let id = NEXT.fetch_add(1, Ordering::Relaxed);
+        let pid = std::process::id();
+        self.tmp_dir
+            .join(format!("{}{}-{}.part", PART_PREFIX, pid, id))
+    }
+}
+
+/// Rename a file, copying it when the temp dir is on another filesystem
+async fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    match tokio::fs::rename(from, to).await {
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+            tokio::fs::copy(from, to).await?;
+            tokio::fs::remove_file(from).await
+        }
+        result => result,
+    }
+}
+
+fn is_pixiv_host(host: &str) -> bool {
+    ["pximg.net", "pixiv.net"]
+        .iter()
+        .any(|domain| is_host_of(host, domain))
+}
+
+fn is_host_of(host: &str, domain: &str) -> bool {
+    host == domain || host.ends_with(&format!(".{}", domain))
+}
+
+/// Check whether every post of a pagination url was published before `since`
+fn page_before(url: &str, since: DateTime<Local>) -> bool {
+    let Ok(url) = Url::parse(url) else {
+        return false;
+    };
+    let Some((_, max_published)) = url
+        .query_pairs()
+        .find(|(key, _)| key == "maxPublishedDatetime")
+    else {
+        return false;
+    };
+    let Ok(max_published) = NaiveDateTime::parse_from_str(&max_published, "%Y-%m-%d %H:%M:%S")
+    else {
+        return false;
+    };
+
+    // fanbox uses JST for pagination, keep a day of margin to be safe
+    let jst = FixedOffset::east_opt(9 * 3600).unwrap();
+    let Some(max_published) = max_published.and_local_timezone(jst).single() else {
+        return false;
+    };
+    max_published + Duration::days(1) < since
+}
+
+/// A download refused by `--max-file-size`, only known once the server sends its size
+#[derive(Debug, Clone, Copy)]
+pub struct FileTooLarge {
+    pub size: u64,
+}
+
+impl std::error::Error for FileTooLarge {}
+
+impl std::fmt::Display for FileTooLarge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "File is larger than the size limit ({} bytes)",
+            self.size
+        )
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, Hash)]
@@ -161,3 +399,11 @@ pub struct FanboxAPIResponse<T> {
 pub struct FanboxAPIResponseError {
     error: String,
 }
+
+impl std::error::Error for FanboxAPIResponseError {}
+
+impl std::fmt::Display for FanboxAPIResponseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
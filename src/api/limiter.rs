@@ -0,0 +1,71 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio::{sync::Mutex, time::Instant};
+
+/// Spread requests evenly to stay under a per-second limit
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    interval: Duration,
+    next: Arc<Mutex<Instant>>,
+}
+
+impl RateLimiter {
+    pub fn new(per_second: f64) -> Option<Self> {
+        if per_second <= 0.0 {
+            return None;
+        }
+        Some(Self {
+            interval: Duration::from_secs_f64(1.0 / per_second),
+            next: Arc::new(Mutex::new(Instant::now())),
+        })
+    }
+
+    pub async fn wait(&self) {
+        let slot = {
+            let mut next = self.next.lock().await;
+            let slot = (*next).max(Instant::now());
+            *next = slot + self.interval;
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+}
+
+/// Token bucket shared by every download to cap the aggregate bandwidth
+#[derive(Debug, Clone)]
+pub struct BandwidthLimiter {
+    rate: f64,
+    bucket: Arc<Mutex<(f64, Instant)>>,
+}
+
+impl BandwidthLimiter {
+    /// Create a limiter of `rate` bytes per second, `0` means unlimited
+    pub fn new(rate: Option<u64>) -> Option<Self> {
+        let rate = rate.filter(|rate| *rate != 0)? as f64;
+        Some(Self {
+            rate,
+            bucket: Arc::new(Mutex::new((rate, Instant::now()))),
+        })
+    }
+
+    /// Take `bytes` from the bucket, waiting until it has been refilled
+    pub async fn consume(&self, bytes: u64) {
+        let wait = {
+            let mut bucket = self.bucket.lock().await;
+            let (tokens, last) = &mut *bucket;
+            let now = Instant::now();
+            // allow bursts of up to one second of traffic
+            *tokens = (*tokens + (now - *last).as_secs_f64() * self.rate).min(self.rate);
+            *last = now;
+            *tokens -= bytes as f64;
+            if *tokens < 0.0 {
+                Duration::from_secs_f64(-*tokens / self.rate)
+            } else {
+                Duration::ZERO
+            }
+        };
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
@@ -1,10 +1,15 @@
 pub mod fanbox;
+pub mod limiter;
+pub mod recorder;
 
 use futures::StreamExt;
+use limiter::{BandwidthLimiter, RateLimiter};
+use log::debug;
 use reqwest::{Client, Response};
 use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
 use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
-use std::sync::Arc;
+use sha2::{Digest, Sha256};
+use std::{error::Error, sync::Arc, time::Instant};
 use tokio::{
     fs::File,
     sync::{Semaphore, SemaphorePermit},
@@ -22,6 +27,8 @@ const RETRY_LIMIT: u32 = 3;
 pub struct ArchiveClient {
     client: Client,
     semaphore: Arc<Semaphore>,
+    rate_limiter: Option<RateLimiter>,
+    bandwidth_limiter: Option<BandwidthLimiter>,
 }
 
 impl ArchiveClient {
@@ -30,26 +37,58 @@ impl ArchiveClient {
         Self {
             client: Client::new(),
             semaphore: Arc::new(Semaphore::new(permits)),
+            rate_limiter: RateLimiter::new(config.per_second_limit()),
+            bandwidth_limiter: BandwidthLimiter::new(config.bandwidth_limit()),
         }
     }
-    async fn client(&self) -> (ClientWithMiddleware, SemaphorePermit) {
+    async fn client(&self) -> (ClientWithMiddleware, SemaphorePermit<'_>) {
         let semaphore = self.semaphore.acquire().await.unwrap();
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.wait().await;
+        }
         let client = self.client_without_semaphore();
         (client, semaphore)
     }
     fn client_without_semaphore(&self) -> ClientWithMiddleware {
         let retry_policy = ExponentialBackoff::builder().build_with_max_retries(RETRY_LIMIT);
-        let client = ClientBuilder::new(self.client.clone())
+        ClientBuilder::new(self.client.clone())
             .with(RetryTransientMiddleware::new_with_policy(retry_policy))
-            .build();
-        client
+            .build()
     }
 
-    async fn download(&self, response: Response, file: &mut File) -> Result<(), reqwest::Error> {
+    /// Write the response into a file, returning the number of bytes and their sha256
+    async fn download(
+        &self,
+        response: Response,
+        file: &mut File,
+    ) -> Result<(u64, String), Box<dyn Error + Send + Sync>> {
+        let started = Instant::now();
         let mut stream = response.bytes_stream();
+        let mut hasher = Sha256::new();
+        let mut total = 0;
         while let Some(bytes) = stream.next().await {
-            tokio::io::copy(&mut bytes?.as_ref(), file).await.unwrap();
+            let bytes = bytes?;
+            if let Some(bandwidth_limiter) = &self.bandwidth_limiter {
+                bandwidth_limiter.consume(bytes.len() as u64).await;
+            }
+            hasher.update(&bytes);
+            total += tokio::io::copy(&mut bytes.as_ref(), file).await?;
+        }
+        let sha256 = hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+
+        let elapsed = started.elapsed().as_secs_f64();
+        if elapsed > 0.0 {
+            debug!(
+                "Downloaded {} bytes in {:.1}s ({:.0} bytes/s)",
+                total,
+                elapsed,
+                total as f64 / elapsed
+            );
         }
-        Ok(())
+        Ok((total, sha256))
     }
 }
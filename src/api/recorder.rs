@@ -0,0 +1,150 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::Duration,
+};
+
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+
+use crate::config::Config;
+
+// shared by every client of this run
+static RECORDED: AtomicU64 = AtomicU64::new(0);
+static RECORD_FULL: AtomicBool = AtomicBool::new(false);
+
+/// Record API responses and downloaded files, or replay them offline
+///
+/// Only response bodies are stored, never request headers or cookies.
+/// example: `record/api/<sha256 of url>.json`, `record/files/<sha256 of url>`
+#[derive(Debug, Clone)]
+pub struct Recorder {
+    dir: PathBuf,
+    mode: RecorderMode,
+    max_size: Option<u64>,
+    latency: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecorderMode {
+    Record,
+    Replay,
+}
+
+impl Recorder {
+    pub fn new(config: &Config) -> Option<Self> {
+        let (dir, mode) = match (config.record(), config.replay()) {
+            (Some(dir), _) => (dir, RecorderMode::Record),
+            (_, Some(dir)) => (dir, RecorderMode::Replay),
+            _ => return None,
+        };
+
+        Some(Self {
+            dir: dir.clone(),
+            mode,
+            max_size: config.record_max_size(),
+            latency: config.replay_latency(),
+        })
+    }
+
+    pub fn replaying(&self) -> bool {
+        self.mode == RecorderMode::Replay
+    }
+
+    pub fn recording(&self) -> bool {
+        self.mode == RecorderMode::Record
+    }
+
+    fn api_path(&self, url: &str) -> PathBuf {
+        self.dir.join("api").join(format!("{}.json", hash_url(url)))
+    }
+
+    fn file_path(&self, url: &str) -> PathBuf {
+        self.dir.join("files").join(hash_url(url))
+    }
+
+    /// Get a recorded API response body
+    pub async fn replay_api(&self, url: &str) -> std::io::Result<Vec<u8>> {
+        tokio::time::sleep(self.latency).await;
+        let path = self.api_path(url);
+        tokio::fs::read(&path).await.map_err(|e| {
+            std::io::Error::new(
+                e.kind(),
+                format!("Replay cache miss: {} ({})", url, path.display()),
+            )
+        })
+    }
+
+    /// Copy a recorded file to `path`, returning the number of bytes copied
+    pub async fn replay_file(&self, url: &str, path: &Path) -> std::io::Result<u64> {
+        tokio::time::sleep(self.latency).await;
+        let source = self.file_path(url);
+        if !source.exists() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Replay cache miss: {} ({})", url, source.display()),
+            ));
+        }
+        tokio::fs::copy(source, path).await
+    }
+
+    pub async fn record_api(&self, url: &str, body: &[u8]) {
+        if self.reserve(body.len() as u64) {
+            let path = self.api_path(url);
+            if let Err(e) = write_file(&path, body).await {
+                warn!("Failed to record {}: {}", url, e);
+            }
+        }
+    }
+
+    pub async fn record_file(&self, url: &str, file: &Path) {
+        let Ok(metadata) = tokio::fs::metadata(file).await else {
+            return;
+        };
+        if self.reserve(metadata.len()) {
+            let path = self.file_path(url);
+            if let Err(e) = copy_file(file, &path).await {
+                warn!("Failed to record {}: {}", url, e);
+            }
+        }
+    }
+
+    /// Reserve space for a recording, returns false once the size cap is reached
+    fn reserve(&self, size: u64) -> bool {
+        let Some(max_size) = self.max_size else {
+            return true;
+        };
+
+        let recorded = RECORDED.fetch_add(size, Ordering::SeqCst);
+        if recorded + size <= max_size {
+            return true;
+        }
+
+        RECORDED.fetch_sub(size, Ordering::SeqCst);
+        if !RECORD_FULL.swap(true, Ordering::SeqCst) {
+            info!("Recording reached the size limit, skip further recording");
+        }
+        false
+    }
+}
+
+fn hash_url(url: &str) -> String {
+    Sha256::digest(url.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+async fn write_file(path: &Path, body: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::write(path, body).await
+}
+
+async fn copy_file(source: &Path, path: &Path) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::copy(source, path).await.map(|_| ())
+}
@@ -0,0 +1,57 @@
+use std::{error::Error, io};
+
+use clap::{CommandFactory, Subcommand};
+use clap_complete::Shell;
+
+use super::Config;
+use crate::verify::verify;
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum Command {
+    /// Generate shell completions
+    Completions {
+        /// Shell to generate completions for
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+    /// Print the resolved configuration as TOML, with the session redacted
+    PrintConfig,
+    /// Hash the archived files again and report missing or corrupt ones
+    Verify {
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+impl Command {
+    /// Run the subcommand instead of archiving
+    pub fn execute(&self, config: &Config) -> Result<(), Box<dyn Error>> {
+        match self {
+            Command::Completions { shell } => {
+                let mut command = Config::command();
+                let name = command.get_name().to_string();
+                clap_complete::generate(*shell, &mut command, name, &mut io::stdout());
+            }
+            Command::PrintConfig => {
+                let mut config = config.clone();
+                config.redact();
+                print!(
+                    "{}",
+                    toml::to_string(&config).expect("Failed to serialize config")
+                );
+            }
+            Command::Verify { json } => {
+                config.init_logger();
+                let report = verify(config)?;
+                if *json {
+                    println!("{}", serde_json::to_string_pretty(&report)?);
+                }
+                if report.exit_code() != 0 {
+                    std::process::exit(report.exit_code());
+                }
+            }
+        }
+        Ok(())
+    }
+}
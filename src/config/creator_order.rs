@@ -0,0 +1,12 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Hash, ValueEnum, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum CreatorOrder {
+    /// Least recently archived first, never archived creators come first
+    #[default]
+    StaleFirst,
+    Alphabetical,
+    Random,
+}
@@ -0,0 +1,71 @@
+use std::{fmt, path::Path, str::FromStr};
+
+use serde::{Serialize, Serializer};
+
+/// Raster formats worth converting, GIFs may be animated
+const CONVERTIBLE_EXTENSIONS: [&str; 4] = ["png", "jpg", "jpeg", "bmp"];
+
+/// Target of `--convert-images`
+/// example: `webp` (lossless), `webp:80`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageConversion {
+    quality: Option<f32>,
+}
+
+impl ImageConversion {
+    /// Lossy quality from 0 to 100, `None` for lossless
+    pub fn quality(&self) -> Option<f32> {
+        self.quality
+    }
+
+    /// Get the filename after conversion, `None` if the file is not converted
+    /// example: `image.png` -> `image.webp`
+    pub fn converted_filename(&self, filename: &str) -> Option<String> {
+        let path = Path::new(filename);
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        if !CONVERTIBLE_EXTENSIONS.contains(&extension.as_str()) {
+            return None;
+        }
+        Some(path.with_extension("webp").to_string_lossy().to_string())
+    }
+}
+
+impl FromStr for ImageConversion {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (format, quality) = match input.split_once(':') {
+            Some((format, quality)) => (format, Some(quality)),
+            None => (input, None),
+        };
+        if !format.eq_ignore_ascii_case("webp") {
+            return Err(format!(
+                "`{}` is not a supported format (expected webp)",
+                format
+            ));
+        }
+        let quality = match quality {
+            Some(quality) => match quality.parse::<f32>() {
+                Ok(quality) if (0.0..=100.0).contains(&quality) => Some(quality),
+                _ => return Err(format!("`{}` is not a quality from 0 to 100", quality)),
+            },
+            None => None,
+        };
+        Ok(Self { quality })
+    }
+}
+
+impl fmt::Display for ImageConversion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.quality {
+            Some(quality) => write!(f, "webp:{}", quality),
+            None => write!(f, "webp"),
+        }
+    }
+}
+
+impl Serialize for ImageConversion {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
@@ -0,0 +1,54 @@
+use std::{fmt, io::Write};
+
+use chrono::Utc;
+use clap::ValueEnum;
+use env_logger::fmt::Formatter;
+use log::{
+    kv::{self, VisitSource},
+    Record,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Hash, ValueEnum, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Pretty,
+    Json,
+}
+
+impl LogFormat {
+    /// Format a record as one JSON object per line
+    pub fn format_json(buf: &mut Formatter, record: &Record) -> std::io::Result<()> {
+        let mut fields = Map::new();
+        fields.insert("timestamp".to_string(), json!(Utc::now().to_rfc3339()));
+        fields.insert("level".to_string(), json!(record.level().as_str()));
+        fields.insert("target".to_string(), json!(record.target()));
+        fields.insert(
+            "message".to_string(),
+            json!(record.args().to_string().trim()),
+        );
+        let _ = record.key_values().visit(&mut FieldVisitor(&mut fields));
+
+        writeln!(buf, "{}", Value::Object(fields))
+    }
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LogFormat::Pretty => write!(f, "pretty"),
+            LogFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+struct FieldVisitor<'a>(&'a mut Map<String, Value>);
+
+impl<'kvs> VisitSource<'kvs> for FieldVisitor<'_> {
+    fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        self.0.insert(key.to_string(), json!(value.to_string()));
+        Ok(())
+    }
+}
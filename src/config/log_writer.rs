@@ -0,0 +1,123 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// Log target writing to stderr and optionally a size-rotated file
+///
+/// Secrets are replaced before anything is written.
+/// example: `fanbox.log`, `fanbox.log.1`, `fanbox.log.2`
+#[derive(Debug)]
+pub struct LogWriter {
+    secrets: Vec<String>,
+    file: Option<LogFile>,
+}
+
+#[derive(Debug)]
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: Option<u64>,
+    keep: usize,
+}
+
+impl LogWriter {
+    pub fn new(secrets: Vec<String>) -> Self {
+        // short values are not real cookies, replacing them would mangle the logs
+        let secrets = secrets.into_iter().filter(|s| s.len() >= 8).collect();
+        Self {
+            secrets,
+            file: None,
+        }
+    }
+
+    /// Also write into `path`, rotating it once it grows over `max_size`
+    pub fn with_file(
+        mut self,
+        path: &Path,
+        max_size: Option<u64>,
+        keep: usize,
+    ) -> io::Result<Self> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        self.file = Some(LogFile {
+            path: path.to_path_buf(),
+            file,
+            size,
+            max_size,
+            keep,
+        });
+        Ok(self)
+    }
+
+    fn redact(&self, text: &str) -> String {
+        self.secrets.iter().fold(text.to_string(), |text, secret| {
+            text.replace(secret, "<redacted>")
+        })
+    }
+}
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = self.redact(&String::from_utf8_lossy(buf));
+        io::stderr().write_all(text.as_bytes())?;
+        if let Some(file) = &mut self.file {
+            file.write(text.as_bytes())?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(file) = &mut self.file {
+            file.file.flush()?;
+        }
+        io::stderr().flush()
+    }
+}
+
+impl LogFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        let len = buf.len() as u64;
+        if self
+            .max_size
+            .is_some_and(|max_size| self.size > 0 && self.size + len > max_size)
+        {
+            self.rotate()?;
+        }
+        self.file.write_all(buf)?;
+        self.size += len;
+        Ok(())
+    }
+
+    /// Shift `log.1` to `log.2` and so on, dropping the oldest file
+    fn rotate(&mut self) -> io::Result<()> {
+        let rotated = |index: usize| {
+            let mut path = self.path.clone().into_os_string();
+            path.push(format!(".{}", index));
+            PathBuf::from(path)
+        };
+
+        if self.keep == 0 {
+            self.file = File::create(&self.path)?;
+        } else {
+            for index in (1..self.keep).rev() {
+                let from = rotated(index);
+                if from.exists() {
+                    fs::rename(from, rotated(index + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated(1))?;
+            self.file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+        }
+        self.size = 0;
+        Ok(())
+    }
+}
@@ -1,43 +1,265 @@
+pub mod command;
+pub mod content_format;
+pub mod creator_order;
+pub mod image_conversion;
+pub mod log_format;
+pub mod log_writer;
+pub mod notify_on;
+pub mod parse;
+pub mod pattern;
 pub mod save_type;
+pub mod sidecar_type;
 
-use clap::{arg, Parser};
+use chrono::{DateTime, Local};
+use clap::{error::ErrorKind, CommandFactory, Parser};
 use clap_verbosity_flag::{InfoLevel, Verbosity};
+use command::Command;
+use content_format::ContentFormat;
+use creator_order::CreatorOrder;
 use dotenv::dotenv;
+use image_conversion::ImageConversion;
+use log_format::LogFormat;
+use log_writer::LogWriter;
+use notify_on::NotifyOn;
+use pattern::CreatorPattern;
 use save_type::SaveType;
-use std::path::PathBuf;
+use serde::{Serialize, Serializer};
+use sidecar_type::SidecarType;
+use std::{path::PathBuf, time::Duration};
 
-use crate::fanbox::{Creator, PostListItem};
+use crate::fanbox::{Creator, PostListItem, PostType};
 
-#[derive(Debug, Clone, Parser, Default)]
+const DEFAULT_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/126.0.0.0 Safari/537.36 Edg/126.0.0.0";
+
+#[derive(Debug, Clone, Parser, Default, Serialize)]
+#[command(subcommand_negates_reqs = true)]
+#[serde(rename_all = "kebab-case")]
 pub struct Config {
+    #[command(subcommand)]
+    #[serde(skip)]
+    command: Option<Command>,
     /// Your `FANBOXSESSID` cookie
-    #[clap(env = "FANBOXSESSID")]
-    session: String,
+    #[clap(env = "FANBOXSESSID", required = true)]
+    session: Option<String>,
+    /// Your pixiv `PHPSESSID` cookie, only sent to pixiv image hosts
+    #[arg(long, env = "PHPSESSID")]
+    pixiv_session: Option<String>,
+    /// User agent sent with every request, keep it fixed when using a `cf_clearance` cookie
+    #[arg(long, default_value = DEFAULT_USER_AGENT, env = "FA_USER_AGENT")]
+    user_agent: String,
     /// Which you path want to save
     #[arg(default_value = "./archive", env = "OUTPUT")]
     output: PathBuf,
+    /// Where unfinished downloads are written (default: `<OUTPUT>/.tmp`)
+    #[arg(long, env = "FA_TMP_DIR")]
+    tmp_dir: Option<PathBuf>,
+    /// Warn when the output or temp directory has less free space than this
+    #[arg(long, default_value = "1G", value_parser = parse::parse_size, env = "FA_MIN_FREE_SPACE")]
+    min_free_space: u64,
     /// Which you type want to save
     #[arg(short, long, default_value = "supporting", env = "SAVE")]
     save: SaveType,
     /// Force download
-    #[arg(short, long)]
+    #[arg(short, long, env = "FA_FORCE")]
     force: bool,
     /// Overwrite existing files
-    #[arg(short, long)]
+    #[arg(short, long, env = "FA_OVERWRITE")]
     overwrite: bool,
-    /// Whitelist of creator IDs
-    #[arg(short, long, num_args = 0..)]
+    /// Wait for another run on the same output to finish instead of exiting
+    #[arg(long, env = "FA_WAIT_LOCK")]
+    wait_lock: bool,
+    /// Keep running and sync again after this interval (e.g. `6h`)
+    #[arg(long, value_parser = parse::parse_interval, env = "FA_WATCH")]
+    #[serde(serialize_with = "serialize_interval")]
+    watch: Option<Duration>,
+    /// Tag the posts of creators you no longer follow or support with `unfollowed`
+    #[arg(long, env = "FA_PRUNE_CREATORS")]
+    prune_creators: bool,
+    /// Only report the creators `--prune-creators` would tag
+    #[arg(long, requires = "prune_creators", env = "FA_PRUNE_DRY_RUN")]
+    prune_dry_run: bool,
+    /// Abort the run on the first failed creator, post or file
+    #[arg(long, env = "FA_FAIL_FAST")]
+    fail_fast: bool,
+    /// Keep the previous version of edited posts as JSON under `revisions/`
+    #[arg(long, env = "FA_KEEP_REVISIONS")]
+    keep_revisions: bool,
+    /// Write a readable `post.md` next to the files of each post
+    #[arg(long, env = "FA_WRITE_MARKDOWN")]
+    write_markdown: bool,
+    /// Archive restricted posts as stubs with their title, excerpt and cover
+    #[arg(long, env = "FA_ARCHIVE_RESTRICTED_STUBS")]
+    archive_restricted_stubs: bool,
+    /// Format of the archived post content
+    #[arg(long, value_enum, default_value_t = ContentFormat::Markdown, env = "FA_CONTENT_FORMAT")]
+    content_format: ContentFormat,
+    /// Level of header blocks, `2` keeps them below the post title
+    #[arg(long, default_value = "2", value_parser = clap::value_parser!(u8).range(1..=6), env = "FA_HEADER_LEVEL")]
+    header_level: u8,
+    /// Fetch the title, description and image of url embeds
+    #[arg(long, env = "FA_FETCH_LINK_PREVIEWS")]
+    fetch_link_previews: bool,
+    /// Archive the text and media of embedded tweets
+    #[arg(long, env = "FA_FETCH_TWEETS")]
+    fetch_tweets: bool,
+    /// Download existing files again when their size differs from the one fanbox reports
+    #[arg(long, env = "FA_VERIFY")]
+    verify: bool,
+    /// Hardlink downloaded files which are identical to an archived one instead of keeping a copy
+    ///
+    /// Files are matched by the sha256 already stored for `verify`, so no second hash is computed.
+    #[arg(long, env = "FA_DEDUPE_FILES")]
+    dedupe_files: bool,
+    /// Check that downloaded images are complete and record their real dimensions
+    #[arg(long, env = "FA_VALIDATE_IMAGES")]
+    validate_images: bool,
+    /// Re-encode downloaded PNG, JPEG and BMP images as WebP when it is smaller
+    /// example: `webp` (lossless), `webp:80`
+    #[arg(long, value_name = "FORMAT[:QUALITY]", env = "FA_CONVERT_IMAGES")]
+    convert_images: Option<ImageConversion>,
+    /// Unpack downloaded zips into a folder next to them and list their files in the post
+    #[arg(long, env = "FA_EXTRACT_ARCHIVES")]
+    extract_archives: bool,
+    /// Keep the zips unpacked by `--extract-archives`
+    #[arg(long, requires = "extract_archives", env = "FA_KEEP_ARCHIVES")]
+    keep_archives: bool,
+    /// Refuse to unpack zips whose content is larger than this
+    #[arg(long, default_value = "4G", value_parser = parse::parse_size, env = "FA_MAX_EXTRACT_SIZE")]
+    max_extract_size: u64,
+    /// Keep the download time as modification time instead of the post's published date
+    #[arg(long, env = "FA_NO_TOUCH_MTIME")]
+    no_touch_mtime: bool,
+    /// Use the post's updated date as modification time instead of the published date
+    #[arg(long, conflicts_with = "no_touch_mtime", env = "FA_MTIME_FROM_UPDATED")]
+    mtime_from_updated: bool,
+    /// Archive these creators instead of the ones you follow or support
+    #[arg(
+        long = "creator",
+        value_name = "CREATOR",
+        value_delimiter = ',',
+        env = "FA_CREATORS"
+    )]
+    creators: Vec<String>,
+    /// Archive these posts (ID or url) instead of whole creators
+    #[arg(long = "post", value_name = "POST", value_delimiter = ',', value_parser = parse::parse_post_id, env = "FA_POSTS")]
+    posts: Vec<String>,
+    /// Order in which creators are archived
+    #[arg(long, value_enum, default_value_t = CreatorOrder::StaleFirst, env = "FA_CREATOR_ORDER")]
+    creator_order: CreatorOrder,
+    /// Whitelist of creator IDs (supports `*` globs, `re:` regex and `@file`)
+    #[arg(short, long, num_args = 0.., value_delimiter = ',', env = "FA_WHITELIST")]
     whitelist: Vec<String>,
-    /// Blacklist of creator IDs
-    #[arg(short, long, num_args = 0..)]
+    /// Blacklist of creator IDs (supports `*` globs, `re:` regex and `@file`)
+    #[arg(short, long, num_args = 0.., value_delimiter = ',', env = "FA_BLACKLIST")]
     blacklist: Vec<String>,
+    #[arg(skip)]
+    #[serde(skip)]
+    whitelist_patterns: Vec<CreatorPattern>,
+    #[arg(skip)]
+    #[serde(skip)]
+    blacklist_patterns: Vec<CreatorPattern>,
     /// Limit download concurrency
-    #[arg(long, default_value = "5")]
+    #[arg(long, default_value = "5", env = "FA_LIMIT")]
     limit: usize,
-    /// Skip free post
-    #[arg(long, name = "skip-free")]
+    /// Number of worker threads, `1` runs everything on one thread (default: one per CPU)
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..), env = "FA_THREADS")]
+    threads: Option<u32>,
+    /// Limit how many posts are fetched and synced at once
+    #[arg(long, default_value = "32", value_parser = clap::value_parser!(u32).range(1..), env = "FA_MAX_IN_FLIGHT_POSTS")]
+    max_in_flight_posts: u32,
+    /// Limit requests per second, fractions allowed (0.5) and 0 disables it
+    #[arg(long, default_value = "2", value_parser = parse::parse_rate, env = "FA_PER_SECOND_LIMIT")]
+    per_second_limit: f64,
+    /// Limit the aggregate download bandwidth per second (e.g. `10M`), 0 is unlimited
+    #[arg(long, value_parser = parse::parse_size, env = "FA_BANDWIDTH_LIMIT")]
+    bandwidth_limit: Option<u64>,
+    /// Skip free post (same as `--min-fee 1`)
+    #[arg(long, name = "skip-free", env = "FA_SKIP_FREE")]
     skip_free: bool,
+    /// Only save posts of these types
+    #[arg(long, value_delimiter = ',', num_args = 0.., value_parser = PostType::VARIANTS, env = "FA_ACCEPT_POST_TYPES")]
+    accept_post_types: Vec<String>,
+    /// Skip adult posts
+    #[arg(long, conflicts_with = "only_adult", env = "FA_SKIP_ADULT")]
+    skip_adult: bool,
+    /// Only save adult posts
+    #[arg(long, env = "FA_ONLY_ADULT")]
+    only_adult: bool,
+    /// Skip posts whose declared fee is lower than this (yen)
+    ///
+    /// Only the fee declared on the post (`feeRequired`) is compared, not your pledge,
+    /// so a free post stays free even if you are a supporter.
+    #[arg(long, env = "FA_MIN_FEE")]
+    min_fee: Option<u32>,
+    /// Skip posts whose declared fee is higher than this (yen)
+    #[arg(long, env = "FA_MAX_FEE")]
+    max_fee: Option<u32>,
+    /// Only save posts published after this date (2024-01-01) or duration ago (90d)
+    #[arg(long, value_parser = parse::parse_datetime, env = "FA_SINCE")]
+    since: Option<DateTime<Local>>,
+    /// Only save posts published before this date (2024-01-01) or duration ago (90d)
+    #[arg(long, value_parser = parse::parse_datetime, env = "FA_UNTIL")]
+    until: Option<DateTime<Local>>,
+    /// Only keep the N most recent posts of each creator (also applies with `--force`)
+    #[arg(long, env = "FA_MAX_POSTS_PER_CREATOR")]
+    max_posts_per_creator: Option<usize>,
+    /// Tags marking a post with a content warning (e.g. `gore,spoiler`)
+    #[arg(long, value_delimiter = ',', num_args = 0.., env = "FA_CONTENT_WARNING_TAGS")]
+    content_warning_tags: Vec<String>,
+    /// Skip files larger than this size (e.g. `500M`, `2G`)
+    #[arg(long, value_parser = parse::parse_size, env = "FA_MAX_FILE_SIZE")]
+    max_file_size: Option<u64>,
+    /// Skip files with these extensions (e.g. `psd,clip,zip`)
+    #[arg(long, value_delimiter = ',', num_args = 0.., env = "FA_EXCLUDE_EXTENSIONS")]
+    exclude_extensions: Vec<String>,
+    /// Write a metadata sidecar next to each file
+    #[arg(long, value_enum, env = "FA_SIDECAR")]
+    sidecar: Option<SidecarType>,
+    /// Record every API response and downloaded file into this directory
+    #[arg(long, conflicts_with = "replay", env = "FA_RECORD")]
+    record: Option<PathBuf>,
+    /// Stop recording once the recording reaches this size (e.g. `2G`)
+    #[arg(long, value_parser = parse::parse_size, requires = "record", env = "FA_RECORD_MAX_SIZE")]
+    record_max_size: Option<u64>,
+    /// Serve every request from a recording instead of the network
+    #[arg(long, env = "FA_REPLAY")]
+    replay: Option<PathBuf>,
+    /// Artificial latency added to each replayed request (milliseconds)
+    #[arg(
+        long,
+        default_value = "0",
+        requires = "replay",
+        env = "FA_REPLAY_LATENCY"
+    )]
+    replay_latency: u64,
+    /// Write a JSON summary of the run into this file
+    #[arg(long, env = "FA_REPORT")]
+    report: Option<PathBuf>,
+    /// Post the run summary to this webhook (Discord webhooks get an embed)
+    #[arg(long, env = "FA_NOTIFY_WEBHOOK")]
+    notify_webhook: Option<String>,
+    /// When to notify the webhook
+    #[arg(long, value_enum, default_value_t = NotifyOn::Always, requires = "notify_webhook", env = "FA_NOTIFY_ON")]
+    notify_on: NotifyOn,
+    /// Log output format
+    #[arg(long, value_enum, default_value_t = LogFormat::Pretty, env = "FA_LOG_FORMAT")]
+    log_format: LogFormat,
+    /// Also write logs into this file
+    #[arg(long, env = "FA_LOG_FILE")]
+    log_file: Option<PathBuf>,
+    /// Rotate the log file once it reaches this size (e.g. `10M`)
+    #[arg(long, value_parser = parse::parse_size, requires = "log_file", env = "FA_LOG_FILE_MAX_SIZE")]
+    log_file_max_size: Option<u64>,
+    /// How many rotated log files to keep
+    #[arg(
+        long,
+        default_value = "3",
+        requires = "log_file",
+        env = "FA_LOG_FILE_KEEP"
+    )]
+    log_file_keep: usize,
     #[command(flatten)]
+    #[serde(skip)]
     pub verbose: Verbosity<InfoLevel>,
 }
 
@@ -45,26 +267,193 @@ impl Config {
     /// Parse the configuration from the environment and command line arguments
     pub fn parse() -> Self {
         dotenv().ok();
-        <Self as Parser>::parse()
+        let mut config = <Self as Parser>::parse();
+
+        if let (Some(since), Some(until)) = (config.since, config.until) {
+            if since > until {
+                Self::command()
+                    .error(
+                        ErrorKind::ArgumentConflict,
+                        "`--since` must not be newer than `--until`",
+                    )
+                    .exit();
+            }
+        }
+
+        config.whitelist = Self::expand_list(&config.whitelist);
+        config.blacklist = Self::expand_list(&config.blacklist);
+        config.whitelist_patterns = Self::compile_patterns(&config.whitelist);
+        config.blacklist_patterns = Self::compile_patterns(&config.blacklist);
+
+        config
+    }
+    /// Expand `@file` entries into the creator IDs listed in the file
+    fn expand_list(list: &[String]) -> Vec<String> {
+        let mut expanded = vec![];
+        for entry in list {
+            let Some(path) = entry.strip_prefix('@') else {
+                expanded.push(entry.clone());
+                continue;
+            };
+
+            let content = match std::fs::read_to_string(path) {
+                Ok(content) => content,
+                Err(e) => Self::command()
+                    .error(ErrorKind::Io, format!("Failed to read `{}`: {}", path, e))
+                    .exit(),
+            };
+            expanded.extend(
+                content
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(String::from),
+            );
+        }
+        expanded
+    }
+    fn compile_patterns(list: &[String]) -> Vec<CreatorPattern> {
+        list.iter()
+            .map(|entry| match CreatorPattern::parse(entry) {
+                Ok(pattern) => pattern,
+                Err(e) => Self::command().error(ErrorKind::ValueValidation, e).exit(),
+            })
+            .collect()
+    }
+    /// Get the subcommand, `None` means archive
+    pub fn subcommand(&self) -> Option<&Command> {
+        self.command.as_ref()
+    }
+    /// Hide the session cookies, e.g. before printing the config
+    pub fn redact(&mut self) {
+        let redacted = Some("<redacted>".to_string());
+        self.session = self.session.as_ref().and(redacted.clone());
+        self.pixiv_session = self.pixiv_session.as_ref().and(redacted);
     }
     /// Create a logger with the configured verbosity level
-    pub fn init_logger(&self) -> () {
-        env_logger::Builder::new()
+    pub fn init_logger(&self) {
+        let mut builder = env_logger::Builder::new();
+        builder
             .filter_level(self.verbose.log_level_filter())
-            .format_target(false)
-            .init();
+            .format_target(false);
+        if self.log_format == LogFormat::Json {
+            builder.format(LogFormat::format_json);
+        }
+
+        // the file needs a pipe target, which turns off the colors of the console
+        if let Some(path) = &self.log_file {
+            let secrets = [&self.session, &self.pixiv_session]
+                .into_iter()
+                .flatten()
+                .map(|session| {
+                    let value = session.split_once('=').map_or(session.as_str(), |(_, v)| v);
+                    value.to_string()
+                })
+                .collect();
+            let writer = match LogWriter::new(secrets).with_file(
+                path,
+                self.log_file_max_size,
+                self.log_file_keep,
+            ) {
+                Ok(writer) => writer,
+                Err(e) => Self::command()
+                    .error(
+                        ErrorKind::Io,
+                        format!("Failed to open log file `{}`: {}", path.display(), e),
+                    )
+                    .exit(),
+            };
+            builder.target(env_logger::Target::Pipe(Box::new(writer)));
+        }
+        builder.init();
     }
     /// Get the session cookie
     pub fn session(&self) -> String {
-        if self.session.starts_with("FANBOXSESSID=") {
-            self.session.clone()
+        let session = self.session.as_deref().unwrap_or_default();
+        if session.starts_with("FANBOXSESSID=") {
+            session.to_string()
+        } else {
+            format!("FANBOXSESSID={}", session)
+        }
+    }
+    /// Get the pixiv session cookie
+    pub fn pixiv_session(&self) -> Option<String> {
+        let session = self.pixiv_session.as_ref()?;
+        if session.starts_with("PHPSESSID=") {
+            Some(session.clone())
         } else {
-            format!("FANBOXSESSID={}", self.session)
+            Some(format!("PHPSESSID={}", session))
         }
     }
+    pub fn user_agent(&self) -> &str {
+        &self.user_agent
+    }
     pub fn overwrite(&self) -> bool {
         self.overwrite
     }
+    pub fn fail_fast(&self) -> bool {
+        self.fail_fast
+    }
+    pub fn keep_revisions(&self) -> bool {
+        self.keep_revisions
+    }
+    pub fn write_markdown(&self) -> bool {
+        self.write_markdown
+    }
+    pub fn archive_restricted_stubs(&self) -> bool {
+        self.archive_restricted_stubs
+    }
+    pub fn content_format(&self) -> ContentFormat {
+        self.content_format
+    }
+    pub fn header_level(&self) -> u8 {
+        self.header_level
+    }
+    pub fn fetch_link_previews(&self) -> bool {
+        self.fetch_link_previews
+    }
+    pub fn fetch_tweets(&self) -> bool {
+        self.fetch_tweets
+    }
+    pub fn verify(&self) -> bool {
+        self.verify
+    }
+    pub fn dedupe_files(&self) -> bool {
+        self.dedupe_files
+    }
+    pub fn validate_images(&self) -> bool {
+        self.validate_images
+    }
+    pub fn convert_images(&self) -> Option<ImageConversion> {
+        self.convert_images
+    }
+    pub fn extract_archives(&self) -> bool {
+        self.extract_archives
+    }
+    pub fn keep_archives(&self) -> bool {
+        self.keep_archives
+    }
+    pub fn max_extract_size(&self) -> u64 {
+        self.max_extract_size
+    }
+    pub fn touch_mtime(&self) -> bool {
+        !self.no_touch_mtime
+    }
+    pub fn mtime_from_updated(&self) -> bool {
+        self.mtime_from_updated
+    }
+    pub fn wait_lock(&self) -> bool {
+        self.wait_lock
+    }
+    pub fn watch(&self) -> Option<Duration> {
+        self.watch
+    }
+    pub fn prune_creators(&self) -> bool {
+        self.prune_creators
+    }
+    pub fn prune_dry_run(&self) -> bool {
+        self.prune_dry_run
+    }
     pub fn accepts(&self) -> SaveType {
         self.save
     }
@@ -72,17 +461,87 @@ impl Config {
     pub fn output(&self) -> &PathBuf {
         &self.output
     }
+    pub fn tmp_dir(&self) -> PathBuf {
+        self.tmp_dir
+            .clone()
+            .unwrap_or_else(|| self.output.join(".tmp"))
+    }
+    pub fn min_free_space(&self) -> u64 {
+        self.min_free_space
+    }
     pub fn limit(&self) -> usize {
         self.limit
     }
+    pub fn threads(&self) -> Option<usize> {
+        self.threads.map(|threads| threads as usize)
+    }
+    pub fn max_in_flight_posts(&self) -> usize {
+        self.max_in_flight_posts as usize
+    }
+    pub fn per_second_limit(&self) -> f64 {
+        self.per_second_limit
+    }
+    pub fn bandwidth_limit(&self) -> Option<u64> {
+        self.bandwidth_limit
+    }
+    pub fn since(&self) -> Option<DateTime<Local>> {
+        self.since
+    }
+    pub fn max_posts_per_creator(&self) -> Option<usize> {
+        self.max_posts_per_creator
+    }
+    pub fn max_file_size(&self) -> Option<u64> {
+        self.max_file_size
+    }
+    /// Check whether a filename has an excluded extension
+    pub fn exclude_extension(&self, filename: &str) -> bool {
+        let Some((_, extension)) = filename.rsplit_once('.') else {
+            return false;
+        };
+        self.exclude_extensions.iter().any(|exclude| {
+            exclude
+                .trim_start_matches('.')
+                .eq_ignore_ascii_case(extension)
+        })
+    }
+    pub fn content_warning_tags(&self) -> &[String] {
+        &self.content_warning_tags
+    }
+    pub fn sidecar(&self) -> Option<SidecarType> {
+        self.sidecar
+    }
+
+    pub fn creators(&self) -> &[String] {
+        &self.creators
+    }
+    pub fn creator_order(&self) -> CreatorOrder {
+        self.creator_order
+    }
+    pub fn posts(&self) -> &[String] {
+        &self.posts
+    }
+    /// Get the creator IDs listed exactly in the whitelist
+    pub fn whitelisted_creators(&self) -> impl Iterator<Item = &str> {
+        self.whitelist_patterns
+            .iter()
+            .filter_map(CreatorPattern::exact)
+    }
 
     pub fn filter_creator(&self, creator: &Creator) -> bool {
-        let creator_id = creator.id().to_string();
+        let creator_id = creator.id();
         let mut accept = true;
 
         accept &= !(self.skip_free && creator.fee() == 0);
-        accept &= self.whitelist.is_empty() || self.whitelist.contains(&creator_id);
-        accept &= !self.blacklist.contains(&creator_id);
+        // blacklist takes precedence over whitelist
+        accept &= self.whitelist_patterns.is_empty()
+            || self
+                .whitelist_patterns
+                .iter()
+                .any(|p| p.matches(creator_id));
+        accept &= !self
+            .blacklist_patterns
+            .iter()
+            .any(|p| p.matches(creator_id));
 
         accept
     }
@@ -90,15 +549,192 @@ impl Config {
     pub fn filter_post(&self, post: &PostListItem) -> bool {
         let mut accept = true;
 
-        // skip_free is true and the post is free
-        accept &= !(self.skip_free && post.fee_required == 0);
+        // fee_required is out of the min_fee/max_fee range
+        accept &= post.fee_required >= self.min_fee();
+        accept &= self
+            .max_fee
+            .is_none_or(|max_fee| post.fee_required <= max_fee);
         // is_restricted means the post is for supporters only
-        accept &= !post.is_restricted;
+        accept &= !post.is_restricted || self.archive_restricted_stubs;
+        // type is not in accept_post_types
+        accept &= self.accept_post_types.is_empty()
+            || self
+                .accept_post_types
+                .iter()
+                .any(|ty| ty == post.ty.as_str());
+        // has_adult_content does not match skip_adult/only_adult
+        accept &= !(self.skip_adult && post.has_adult_content);
+        accept &= !self.only_adult || post.has_adult_content;
+        // published_datetime is out of the since/until range
+        accept &= self
+            .since
+            .is_none_or(|since| post.published_datetime >= since);
+        accept &= self
+            .until
+            .is_none_or(|until| post.published_datetime <= until);
 
         accept
     }
 
+    pub fn report(&self) -> Option<&PathBuf> {
+        self.report.as_ref()
+    }
+    pub fn notify_webhook(&self) -> Option<&str> {
+        self.notify_webhook.as_deref()
+    }
+    pub fn notify_on(&self) -> NotifyOn {
+        self.notify_on
+    }
+    pub fn record(&self) -> Option<&PathBuf> {
+        self.record.as_ref()
+    }
+    pub fn record_max_size(&self) -> Option<u64> {
+        self.record_max_size
+    }
+    pub fn replay(&self) -> Option<&PathBuf> {
+        self.replay.as_ref()
+    }
+    pub fn replay_latency(&self) -> Duration {
+        Duration::from_millis(self.replay_latency)
+    }
+
+    /// Get the minimum post fee, `--skip-free` implies at least 1
+    pub fn min_fee(&self) -> u32 {
+        let min_fee = self.min_fee.unwrap_or_default();
+        if self.skip_free {
+            min_fee.max(1)
+        } else {
+            min_fee
+        }
+    }
+
     pub fn force(&self) -> bool {
         self.force
     }
 }
+
+/// Serialize an interval the way it is parsed, e.g. `21600s`
+fn serialize_interval<S: Serializer>(
+    interval: &Option<Duration>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    interval
+        .map(|interval| format!("{}s", interval.as_secs()))
+        .serialize(serializer)
+}
+
+/// Held by the tests reading or changing environment variables,
+/// the environment is shared by the tests running in parallel
+#[cfg(test)]
+pub static ENV_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fanbox::User;
+
+    fn creator(id: &str, fee: u32) -> Creator {
+        Creator {
+            creator_id: id.to_string(),
+            user: User {
+                icon_url: None,
+                name: id.to_string(),
+                user_id: "1".to_string(),
+            },
+            fee,
+        }
+    }
+
+    fn filter(whitelist: &[&str], blacklist: &[&str]) -> Config {
+        let patterns = |list: &[&str]| {
+            list.iter()
+                .map(|entry| CreatorPattern::parse(entry).unwrap())
+                .collect()
+        };
+        Config {
+            whitelist_patterns: patterns(whitelist),
+            blacklist_patterns: patterns(blacklist),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn filter_creator_without_lists() {
+        assert!(filter(&[], &[]).filter_creator(&creator("foo", 500)));
+    }
+
+    #[test]
+    fn filter_creator_by_whitelist() {
+        let config = filter(&["foo*", "re:^ba[rz]$"], &[]);
+        assert!(config.filter_creator(&creator("foobar", 500)));
+        assert!(config.filter_creator(&creator("baz", 500)));
+        assert!(!config.filter_creator(&creator("qux", 500)));
+    }
+
+    #[test]
+    fn filter_creator_by_blacklist() {
+        let config = filter(&[], &["foo"]);
+        assert!(!config.filter_creator(&creator("foo", 500)));
+        assert!(config.filter_creator(&creator("foobar", 500)));
+    }
+
+    #[test]
+    fn blacklist_takes_precedence_over_whitelist() {
+        let config = filter(&["foo*"], &["foobar"]);
+        assert!(config.filter_creator(&creator("foo", 500)));
+        assert!(!config.filter_creator(&creator("foobar", 500)));
+    }
+
+    #[test]
+    fn filter_creator_skips_free() {
+        let config = Config {
+            skip_free: true,
+            ..filter(&["foo"], &[])
+        };
+        assert!(!config.filter_creator(&creator("foo", 0)));
+        assert!(config.filter_creator(&creator("foo", 500)));
+    }
+
+    /// An environment variable removed again once dropped, set while holding `ENV_LOCK`
+    struct EnvVar(&'static str);
+
+    impl EnvVar {
+        fn set(name: &'static str, value: &str) -> Self {
+            std::env::set_var(name, value);
+            Self(name)
+        }
+    }
+
+    impl Drop for EnvVar {
+        fn drop(&mut self) {
+            std::env::remove_var(self.0);
+        }
+    }
+
+    #[test]
+    fn cli_overrides_env() {
+        let _env = ENV_LOCK.blocking_lock();
+        let _limit = EnvVar::set("FA_LIMIT", "3");
+        let config = Config::try_parse_from(["fanbox-archive", "session"]).unwrap();
+        assert_eq!(config.limit(), 3);
+
+        let config = Config::try_parse_from(["fanbox-archive", "session", "--limit", "7"]).unwrap();
+        assert_eq!(config.limit(), 7);
+    }
+
+    #[test]
+    fn env_overrides_dotenv() {
+        let _env = ENV_LOCK.blocking_lock();
+        let path = std::env::temp_dir().join(format!("fanbox-archive-{}.env", std::process::id()));
+        std::fs::write(&path, "FA_MAX_FEE=200\nFA_THREADS=4\n").unwrap();
+        let _max_fee = EnvVar::set("FA_MAX_FEE", "100");
+        let _threads = EnvVar("FA_THREADS");
+        dotenv::from_path(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let config = Config::try_parse_from(["fanbox-archive", "session"]).unwrap();
+        assert_eq!(config.max_fee, Some(100));
+        // only missing variables are read from `.env`
+        assert_eq!(config.threads(), Some(4));
+    }
+}
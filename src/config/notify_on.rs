@@ -0,0 +1,12 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Hash, ValueEnum, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyOn {
+    /// Notify after every run
+    #[default]
+    Always,
+    /// Only notify when something failed
+    Failure,
+}
@@ -0,0 +1,106 @@
+use chrono::{DateTime, Duration, Local, NaiveDate, TimeZone};
+
+/// Parse a date (`2024-01-01`) or a relative duration (`90d`) into a datetime
+pub fn parse_datetime(input: &str) -> Result<DateTime<Local>, String> {
+    if let Ok(date) = NaiveDate::parse_from_str(input, "%Y-%m-%d") {
+        let datetime = date.and_hms_opt(0, 0, 0).unwrap();
+        return Local
+            .from_local_datetime(&datetime)
+            .earliest()
+            .ok_or_else(|| format!("`{}` is not a valid local date", input));
+    }
+
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(input) {
+        return Ok(datetime.with_timezone(&Local));
+    }
+
+    match parse_duration(input) {
+        Ok(duration) => Ok(Local::now() - duration),
+        Err(_) => Err(format!(
+            "`{}` is neither a date (2024-01-01) nor a duration (90d)",
+            input
+        )),
+    }
+}
+
+/// Parse a duration like `30s`, `15m`, `6h`, `90d` or `2w`
+pub fn parse_duration(input: &str) -> Result<Duration, String> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    let (value, unit) = input.split_at(split);
+    let value: i64 = value
+        .parse()
+        .map_err(|_| format!("`{}` is not a valid duration", input))?;
+
+    match unit {
+        "s" => Ok(Duration::seconds(value)),
+        "m" => Ok(Duration::minutes(value)),
+        "h" => Ok(Duration::hours(value)),
+        "d" => Ok(Duration::days(value)),
+        "w" => Ok(Duration::weeks(value)),
+        _ => Err(format!(
+            "`{}` has an unknown unit (expected s, m, h, d or w)",
+            input
+        )),
+    }
+}
+
+/// Parse a positive interval like `6h` into a std duration
+pub fn parse_interval(input: &str) -> Result<std::time::Duration, String> {
+    match parse_duration(input)?.to_std() {
+        Ok(interval) if !interval.is_zero() => Ok(interval),
+        _ => Err(format!("`{}` is not a positive interval", input)),
+    }
+}
+
+/// Parse a request rate, `0` means unlimited
+pub fn parse_rate(input: &str) -> Result<f64, String> {
+    match input.parse::<f64>() {
+        Ok(rate) if rate.is_finite() && rate >= 0.0 => Ok(rate),
+        _ => Err(format!("`{}` is not a non-negative number", input)),
+    }
+}
+
+/// Parse a size like `500M`, `2G` or `1024`
+pub fn parse_size(input: &str) -> Result<u64, String> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(input.len());
+    let (value, unit) = input.split_at(split);
+    let value: f64 = value
+        .parse()
+        .map_err(|_| format!("`{}` is not a valid size", input))?;
+
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        _ => {
+            return Err(format!(
+                "`{}` has an unknown unit (expected B, K, M, G or T)",
+                input
+            ))
+        }
+    };
+    Ok((value * multiplier as f64) as u64)
+}
+
+/// Parse a post ID or url like `https://xxx.fanbox.cc/posts/1234567`
+pub fn parse_post_id(input: &str) -> Result<String, String> {
+    let input = input.trim().trim_end_matches('/');
+    let id = match input.rsplit_once("/posts/") {
+        Some((_, id)) => id.split(['?', '#']).next().unwrap_or_default(),
+        None => input,
+    };
+
+    if !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()) {
+        Ok(id.to_string())
+    } else {
+        Err(format!("`{}` is neither a post ID nor a post url", input))
+    }
+}
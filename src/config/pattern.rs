@@ -0,0 +1,92 @@
+use regex::Regex;
+
+/// A creator ID matcher for whitelist and blacklist
+/// example: `foo`, `foo*` or `re:^foo.*`
+#[derive(Debug, Clone)]
+pub enum CreatorPattern {
+    Exact(String),
+    Pattern(Regex),
+}
+
+impl CreatorPattern {
+    pub fn parse(input: &str) -> Result<Self, String> {
+        if let Some(pattern) = input.strip_prefix("re:") {
+            return Regex::new(pattern)
+                .map(CreatorPattern::Pattern)
+                .map_err(|e| format!("Invalid pattern `{}`: {}", input, e));
+        }
+
+        if input.contains(['*', '?']) {
+            let pattern = input
+                .split('*')
+                .map(|part| {
+                    part.split('?')
+                        .map(regex::escape)
+                        .collect::<Vec<_>>()
+                        .join(".")
+                })
+                .collect::<Vec<_>>()
+                .join(".*");
+            return Regex::new(&format!("^{}$", pattern))
+                .map(CreatorPattern::Pattern)
+                .map_err(|e| format!("Invalid pattern `{}`: {}", input, e));
+        }
+
+        Ok(CreatorPattern::Exact(input.to_string()))
+    }
+
+    /// Get the creator ID of an exact pattern
+    pub fn exact(&self) -> Option<&str> {
+        match self {
+            CreatorPattern::Exact(id) => Some(id),
+            CreatorPattern::Pattern(_) => None,
+        }
+    }
+
+    pub fn matches(&self, creator_id: &str) -> bool {
+        match self {
+            CreatorPattern::Exact(id) => id == creator_id,
+            CreatorPattern::Pattern(regex) => regex.is_match(creator_id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_pattern() {
+        let pattern = CreatorPattern::parse("foo").unwrap();
+        assert_eq!(pattern.exact(), Some("foo"));
+        assert!(pattern.matches("foo"));
+        assert!(!pattern.matches("foobar"));
+    }
+
+    #[test]
+    fn glob_pattern() {
+        let pattern = CreatorPattern::parse("foo*").unwrap();
+        assert_eq!(pattern.exact(), None);
+        assert!(pattern.matches("foo"));
+        assert!(pattern.matches("foobar"));
+        assert!(!pattern.matches("barfoo"));
+
+        let pattern = CreatorPattern::parse("a?c.d").unwrap();
+        assert!(pattern.matches("abc.d"));
+        // the rest is matched literally
+        assert!(!pattern.matches("abcxd"));
+    }
+
+    #[test]
+    fn regex_pattern() {
+        let pattern = CreatorPattern::parse("re:^foo[0-9]+$").unwrap();
+        assert!(pattern.matches("foo12"));
+        assert!(!pattern.matches("foo"));
+    }
+
+    #[test]
+    fn invalid_regex_fails_to_parse() {
+        let error = CreatorPattern::parse("re:foo(").unwrap_err();
+        assert!(error.starts_with("Invalid pattern `re:foo(`"), "{}", error);
+    }
+}
@@ -3,10 +3,12 @@ use std::fmt;
 use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Serialize, Debug, Clone, Copy, Hash, ValueEnum, PartialEq, Eq)]
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Hash, ValueEnum, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
 pub enum SaveType {
     All,
     Following,
+    #[default]
     Supporting,
 }
 
@@ -29,12 +31,6 @@ impl SaveType {
     }
 }
 
-impl Default for SaveType {
-    fn default() -> Self {
-        SaveType::Supporting
-    }
-}
-
 impl fmt::Display for SaveType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -0,0 +1,26 @@
+use std::fmt;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Hash, ValueEnum, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SidecarType {
+    Json,
+    Xmp,
+}
+
+impl SidecarType {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SidecarType::Json => "json",
+            SidecarType::Xmp => "xmp",
+        }
+    }
+}
+
+impl fmt::Display for SidecarType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
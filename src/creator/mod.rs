@@ -1,13 +1,30 @@
-use std::{collections::HashSet, error::Error, ops::Deref};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    hash::{BuildHasher, RandomState},
+    ops::Deref,
+};
 
 use chrono::{DateTime, Utc};
-use log::info;
+use log::{info, warn};
 use post_archiver::{Author, AuthorId, FileMetaId, Link};
 use rusqlite::{params, Connection, OptionalExtension};
 
-use crate::{api::fanbox::FanboxClient, config::Config, fanbox::Creator};
+use crate::{
+    api::fanbox::FanboxClient,
+    config::{creator_order::CreatorOrder, Config},
+    fanbox::Creator,
+    post::{get_or_insert_tag, get_source_range},
+};
+
+/// Tag of the posts of creators which are neither followed nor supported anymore
+const UNFOLLOWED_TAG: &str = "unfollowed";
 
 pub async fn get_creators(config: &Config) -> Result<Vec<Creator>, Box<dyn Error>> {
+    if !config.creators().is_empty() {
+        return get_explicit_creators(config).await;
+    }
+
     let accepts = config.accepts();
     info!("Accepts:");
     for accept in accepts.list() {
@@ -15,7 +32,7 @@ pub async fn get_creators(config: &Config) -> Result<Vec<Creator>, Box<dyn Error
     }
     info!("");
 
-    let client = FanboxClient::new(&config);
+    let client = FanboxClient::new(config);
     let mut creators: HashSet<Creator> = HashSet::new();
     info!("Checking creators");
     if accepts.accept_following() {
@@ -27,13 +44,27 @@ pub async fn get_creators(config: &Config) -> Result<Vec<Creator>, Box<dyn Error
     if accepts.accept_supporting() {
         let supporting = client.get_supporting_creators().await?;
         info!(" + Supporting: {} found", supporting.len());
-        creators.extend(supporting.into_iter().map(|f| f.into()));
+        merge_supporting(&mut creators, supporting.into_iter().map(|s| s.into()));
+    }
+
+    // whitelisted creators you neither follow nor support
+    for creator_id in config.whitelisted_creators() {
+        if creators.iter().any(|creator| creator.id() == creator_id) {
+            continue;
+        }
+        match client.get_creator(creator_id).await {
+            Ok(creator) => {
+                info!(" + Whitelist: {} added", creator_id);
+                creators.insert(creator.into());
+            }
+            Err(e) => warn!(" + Whitelist: {} skipped ({})", creator_id, e),
+        }
     }
     info!("");
 
     let total = creators.len();
     info!("Total: {} creators", total);
-    creators.retain(|c| config.filter_creator(&c));
+    creators.retain(|c| config.filter_creator(c));
     let filtered = creators.len();
     info!("Excluded: {} creators", total - filtered);
     info!("Filtered: {} creators", filtered);
@@ -41,11 +72,127 @@ pub async fn get_creators(config: &Config) -> Result<Vec<Creator>, Box<dyn Error
     Ok(creators.into_iter().collect())
 }
 
-pub fn display_creators(creators: &Vec<Creator>) {
+/// Add the supporting creators to the followed ones
+/// creators are unique by id, prefer the supporting entry which carries the fee
+fn merge_supporting(creators: &mut HashSet<Creator>, supporting: impl Iterator<Item = Creator>) {
+    for creator in supporting {
+        creators.replace(creator);
+    }
+}
+
+/// Tag the posts of archived creators which are neither followed nor supported anymore
+///
+/// Posts and files are never removed, creators coming back lose the tag.
+pub async fn prune_creators(config: &Config, conn: &mut Connection) -> Result<(), Box<dyn Error>> {
+    let client = FanboxClient::new(config);
+    let mut current: HashSet<String> = HashSet::new();
+    let following = client.get_following_creators().await?;
+    current.extend(following.into_iter().map(|f| Creator::from(f).id().clone()));
+    let supporting = client.get_supporting_creators().await?;
+    current.extend(
+        supporting
+            .into_iter()
+            .map(|f| Creator::from(f).id().clone()),
+    );
+
+    let tx = conn.transaction()?;
+    // a renamed creator has an alias for each of its ids
+    let mut authors: Vec<(AuthorId, String, Vec<String>)> = vec![];
+    {
+        let mut stmt = tx.prepare(
+            "SELECT author_alias.source, authors.id, authors.name FROM author_alias
+            JOIN authors ON authors.id = author_alias.target
+            WHERE author_alias.source LIKE 'fanbox:%'
+            ORDER BY authors.id",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let source: String = row.get(0)?;
+            Ok((source, row.get::<_, AuthorId>(1)?, row.get::<_, String>(2)?))
+        })?;
+        for row in rows {
+            let (source, id, name) = row?;
+            let creator_id = source.trim_start_matches("fanbox:").to_string();
+            match authors.last_mut() {
+                Some((last, _, creator_ids)) if *last == id => creator_ids.push(creator_id),
+                _ => authors.push((id, name, vec![creator_id])),
+            }
+        }
+    }
+
+    // an author is only unfollowed when none of its ids is followed or supported
+    let (followed, unfollowed): (Vec<_>, Vec<_>) = authors
+        .iter()
+        .partition(|(_, _, creator_ids)| creator_ids.iter().any(|id| current.contains(id)));
+    info!("Unfollowed: {} creators", unfollowed.len());
+    for (_, name, creator_ids) in unfollowed.iter() {
+        info!(" + {} `{}`", creator_ids.join(", "), name);
+    }
+    if config.prune_dry_run() {
+        info!("");
+        return Ok(());
+    }
+
+    let tag = get_or_insert_tag(&tx, UNFOLLOWED_TAG)?;
+    {
+        let mut insert_stmt = tx.prepare(
+            "INSERT OR IGNORE INTO post_tags (post,tag) SELECT id, ?1 FROM posts WHERE author = ?2",
+        )?;
+        let mut delete_stmt = tx.prepare(
+            "DELETE FROM post_tags WHERE tag = ?1 AND post IN (SELECT id FROM posts WHERE author = ?2)",
+        )?;
+        for (id, _, _) in followed {
+            delete_stmt.execute(params![tag, id])?;
+        }
+        for (id, _, _) in unfollowed {
+            insert_stmt.execute(params![tag, id])?;
+        }
+    }
+    tx.commit()?;
+    info!("");
+    Ok(())
+}
+
+/// Get the creators given by `--creator`, ignoring your following and supporting lists
+async fn get_explicit_creators(config: &Config) -> Result<Vec<Creator>, Box<dyn Error>> {
+    info!("Creators:");
+    for creator_id in config.creators() {
+        info!(" + {}", creator_id);
+    }
+    info!("");
+
+    let client = FanboxClient::new(config);
+    let mut creators: HashSet<Creator> = HashSet::new();
+    info!("Checking creators");
+    for creator_id in config.creators() {
+        match client.get_creator(creator_id).await {
+            Ok(creator) => {
+                creators.insert(creator.into());
+            }
+            Err(e) => warn!(" + {} skipped ({})", creator_id, e),
+        }
+    }
+    info!(" + {} found", creators.len());
+    info!("");
+    Ok(creators.into_iter().collect())
+}
+
+pub fn display_creators(conn: &Connection, creators: &[Creator]) -> Result<(), rusqlite::Error> {
     if log::log_enabled!(log::Level::Info) {
-        let mut creators = creators.clone();
+        let mut creators = creators.to_vec();
         creators.sort_by(|a, b| a.id().cmp(b.id()));
 
+        let mut stmt = conn.prepare(
+            "SELECT COUNT(*), MAX(published) FROM posts WHERE source >= ? AND source < ?",
+        )?;
+        let mut archived = vec![];
+        for creator in creators.iter() {
+            let (start, end) = get_source_range(creator.id());
+            let (count, last): (usize, Option<DateTime<Utc>>) =
+                stmt.query_row(params![start, end], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            let last = last.map_or("-".to_string(), |last| last.format("%Y-%m-%d").to_string());
+            archived.push((count, last));
+        }
+
         let (mut id_width, mut fee_width) = (11_usize, 5_usize);
         for creator in creators.iter() {
             id_width = creator.id().len().max(id_width);
@@ -53,24 +200,27 @@ pub fn display_creators(creators: &Vec<Creator>) {
         }
 
         info!(
-            "+-{:-<id_width$}-+-{:-<fee_width$}--+-{}------- - -",
-            " CreatorId ", " Fee ", " Name "
+            "+-{:-<id_width$}-+-{:-<fee_width$}--+-{:-<7}-+-{:-<10}-+-{}------- - -",
+            " CreatorId ", " Fee ", " Posts ", " Last Post ", " Name "
         );
-        for creator in creators.iter() {
+        for (creator, (count, last)) in creators.iter().zip(archived) {
             info!(
-                "| {:id_width$} | {:fee_width$}$ | {}",
+                "| {:id_width$} | {:fee_width$}$ | {:>7} | {:10} | {}",
                 creator.id(),
                 creator.fee(),
+                count,
+                last,
                 creator.name()
             );
         }
         info!(
-            "+-{}-+-{}--+------------ - -",
+            "+-{}-+-{}--+---------+------------+------------ - -",
             "-".to_string().repeat(id_width),
             "-".to_string().repeat(fee_width)
         );
         info!("");
     }
+    Ok(())
 }
 
 pub fn sync_creators(
@@ -86,16 +236,32 @@ pub fn sync_creators(
         let mut insert_author_stmt =
             tx.prepare("INSERT INTO authors (name,links) VALUES (?,?) RETURNING *")?;
         let mut insert_alias_stmt =
-            tx.prepare("INSERT INTO author_alias (source,target) VALUES (?,?)")?;
+            tx.prepare("INSERT OR IGNORE INTO author_alias (source,target) VALUES (?,?)")?;
 
         for creator in creators {
             let alias = format!("fanbox:{}", creator.id());
+            // the pixiv user id stays the same when the creator id is renamed
+            let pixiv_alias = format!("pixiv:{}", creator.user().id());
             let link = || Link::new("fanbox", &format!("https://{}.fanbox.cc/", creator.id()));
 
-            let author = match get_alias_stmt
+            let author_id = match get_alias_stmt
                 .query_row([&alias], |row| row.get::<_, AuthorId>(0))
                 .optional()?
             {
+                Some(id) => Some(id),
+                None => {
+                    let renamed = get_alias_stmt
+                        .query_row([&pixiv_alias], |row| row.get::<_, AuthorId>(0))
+                        .optional()?;
+                    if let Some(id) = renamed {
+                        info!(" + Creator renamed to {}, keep author {}", creator.id(), id);
+                        insert_alias_stmt.execute(params![alias, id])?;
+                    }
+                    renamed
+                }
+            };
+
+            let author = match author_id {
                 Some(id) => {
                     // it should be safe to unwrap here
                     // because author_alias has foreign key constraint
@@ -131,6 +297,7 @@ pub fn sync_creators(
                     author
                 }
             };
+            insert_alias_stmt.execute(params![pixiv_alias, author.id])?;
 
             fn row_to_author(row: &rusqlite::Row) -> Result<Author, rusqlite::Error> {
                 let id: AuthorId = AuthorId::new(row.get("id")?);
@@ -161,6 +328,56 @@ pub fn sync_creators(
     Ok(list)
 }
 
+/// Sort creators by `--creator-order`
+pub fn sort_creators(
+    conn: &Connection,
+    config: &Config,
+    creators: &mut [SyncedCreator],
+) -> Result<(), rusqlite::Error> {
+    match config.creator_order() {
+        CreatorOrder::StaleFirst => {
+            let archived = get_archived_times(conn)?;
+            // never archived creators have no time, and come first
+            creators.sort_by_key(|creator| archived.get(&creator.author().id).copied());
+        }
+        CreatorOrder::Alphabetical => creators.sort_by(|a, b| a.id().cmp(b.id())),
+        CreatorOrder::Random => {
+            // hashers are randomly seeded on each run
+            let state = RandomState::new();
+            creators.sort_by_cached_key(|creator| state.hash_one(creator.id()));
+        }
+    }
+    Ok(())
+}
+
+/// Time each author was last archived, kept out of `authors.updated`
+/// which is the time of their latest post, maintained by the post-archiver triggers
+const ARCHIVED_TABLE_SQL: &str = "CREATE TABLE IF NOT EXISTS fanbox_archived (
+    author INTEGER NOT NULL PRIMARY KEY,
+    archived DATETIME NOT NULL,
+    FOREIGN KEY (author) REFERENCES authors (id) ON DELETE CASCADE
+)";
+
+fn get_archived_times(
+    conn: &Connection,
+) -> Result<HashMap<AuthorId, DateTime<Utc>>, rusqlite::Error> {
+    conn.execute(ARCHIVED_TABLE_SQL, [])?;
+    let mut stmt = conn.prepare("SELECT author, archived FROM fanbox_archived")?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect()
+}
+
+/// Mark an author as archived now, used by `--creator-order stale-first`
+pub fn mark_archived(conn: &Connection, author: AuthorId) -> Result<(), rusqlite::Error> {
+    conn.execute(ARCHIVED_TABLE_SQL, [])?;
+    conn.execute(
+        "INSERT INTO fanbox_archived (author, archived) VALUES (?1, ?2)
+        ON CONFLICT (author) DO UPDATE SET archived = ?2",
+        params![author, Utc::now()],
+    )?;
+    Ok(())
+}
+
 pub struct SyncedCreator {
     creator: Creator,
     author: Author,
@@ -182,3 +399,37 @@ impl Deref for SyncedCreator {
         &self.creator
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fanbox::User;
+
+    fn creator(id: &str, fee: u32) -> Creator {
+        Creator {
+            creator_id: id.to_string(),
+            user: User {
+                icon_url: None,
+                name: id.to_string(),
+                user_id: id.to_string(),
+            },
+            fee,
+        }
+    }
+
+    #[test]
+    fn supported_creator_keeps_the_fee() {
+        let mut creators: HashSet<Creator> = [creator("foo", 0), creator("bar", 0)].into();
+        merge_supporting(
+            &mut creators,
+            [creator("foo", 500), creator("baz", 300)].into_iter(),
+        );
+
+        let mut fees: Vec<_> = creators
+            .iter()
+            .map(|c| (c.id().as_str(), c.fee()))
+            .collect();
+        fees.sort();
+        assert_eq!(fees, [("bar", 0), ("baz", 300), ("foo", 500)]);
+    }
+}
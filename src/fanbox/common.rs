@@ -19,7 +19,7 @@ impl User {
     }
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone, Hash)]
+#[derive(Deserialize, Serialize, Debug, Clone, Hash, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub enum PostType {
     Image,
@@ -28,4 +28,28 @@ pub enum PostType {
     Article,
     Video,
     Entry,
+    #[serde(untagged)]
+    Other(String),
+}
+
+impl PostType {
+    pub const VARIANTS: [&'static str; 6] = ["image", "text", "file", "article", "video", "entry"];
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            PostType::Image => "image",
+            PostType::Text => "text",
+            PostType::File => "file",
+            PostType::Article => "article",
+            PostType::Video => "video",
+            PostType::Entry => "entry",
+            PostType::Other(ty) => ty,
+        }
+    }
+}
+
+impl Default for PostType {
+    fn default() -> Self {
+        PostType::Other("unknown".to_string())
+    }
 }
@@ -5,7 +5,7 @@ use std::{hash::Hash, ops::Deref};
 
 use serde::{Deserialize, Serialize};
 
-use super::common::User;
+use super::{common::User, Post};
 
 pub use following::*;
 pub use supporting::*;
@@ -29,6 +29,16 @@ impl Creator {
     }
 }
 
+impl From<&Post> for Creator {
+    fn from(post: &Post) -> Self {
+        Creator {
+            creator_id: post.creator_id.clone(),
+            user: post.user.clone(),
+            fee: 0,
+        }
+    }
+}
+
 impl Deref for Creator {
     type Target = User;
 
@@ -1,12 +1,13 @@
 use std::collections::BTreeMap;
 
 use post_archiver::utils::get_mime;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
 
 use super::super::FollowingCreator;
 use super::PostListItem;
 
-#[derive(Deserialize, Serialize, Debug, Clone, Hash)]
+#[derive(Deserialize, Serialize, Debug, Clone, Hash, Default)]
 #[serde(rename_all = "camelCase", tag = "type")]
 pub struct PostBody {
     pub text: Option<String>,
@@ -18,6 +19,12 @@ pub struct PostBody {
     pub file_map: Option<BTreeMap<String, PostFile>>,
     pub embed_map: Option<BTreeMap<String, PostEmbed>>,
     pub url_embed_map: Option<BTreeMap<String, PostTextEmbed>>,
+    /// Previews of url embeds by their id, see `--fetch-link-previews`
+    #[serde(skip)]
+    pub link_previews: BTreeMap<String, LinkPreview>,
+    /// Embedded tweets by their embed id, `None` once deleted, see `--fetch-tweets`
+    #[serde(skip)]
+    pub tweets: BTreeMap<String, Option<Tweet>>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, Hash)]
@@ -26,21 +33,60 @@ pub enum PostBlock {
     P {
         text: String,
         styles: Option<Vec<PostBlockStyle>>,
+        links: Option<Vec<PostBlockLink>>,
     },
     Header {
         text: String,
         styles: Option<Vec<PostBlockStyle>>,
+        links: Option<Vec<PostBlockLink>>,
     },
     #[serde(rename_all = "camelCase")]
-    Image { image_id: String },
+    Image {
+        image_id: String,
+    },
     #[serde(rename_all = "camelCase")]
-    File { file_id: String },
+    File {
+        file_id: String,
+    },
     #[serde(rename_all = "camelCase")]
-    Embed { embed_id: String },
+    Embed {
+        embed_id: String,
+    },
     #[serde(rename_all = "camelCase")]
-    UrlEmbed { url_embed_id: String },
+    UrlEmbed {
+        url_embed_id: String,
+    },
     #[serde(rename_all = "camelCase")]
-    Video { video_id: String },
+    Video {
+        video_id: String,
+    },
+    HorizontalRule,
+    /// Any block type this archiver doesn't know yet
+    #[serde(untagged)]
+    Unknown(UnknownBlock),
+}
+
+/// The raw JSON of an unknown block, so it can still be kept
+#[derive(Debug, Clone, Hash)]
+pub struct UnknownBlock {
+    pub ty: String,
+    pub raw: String,
+}
+
+impl<'de> Deserialize<'de> for UnknownBlock {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        let ty = value["type"].as_str().unwrap_or_default().to_string();
+        let raw = value.to_string();
+        Ok(Self { ty, raw })
+    }
+}
+
+impl Serialize for UnknownBlock {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let value: Value = serde_json::from_str(&self.raw).map_err(serde::ser::Error::custom)?;
+        value.serialize(serializer)
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, Hash)]
@@ -52,6 +98,14 @@ pub struct PostBlockStyle {
     pub length: u32,
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone, Hash)]
+#[serde(rename_all = "camelCase")]
+pub struct PostBlockLink {
+    pub offset: u32,
+    pub length: u32,
+    pub url: String,
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone, Hash)]
 #[serde(rename_all = "camelCase")]
 pub struct PostImage {
@@ -147,3 +201,23 @@ pub enum PostTextEmbed {
         host: String,
     },
 }
+
+/// What a linked page shows about itself
+#[derive(Debug, Clone, Default, Hash)]
+pub struct LinkPreview {
+    pub url: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub image: Option<String>,
+}
+
+/// An embedded tweet, as far as it is archived
+#[derive(Debug, Clone, Default, Hash)]
+pub struct Tweet {
+    pub url: String,
+    pub name: String,
+    pub screen_name: String,
+    pub text: String,
+    /// Urls of the full size photos and videos
+    pub media: Vec<String>,
+}
@@ -1,7 +1,7 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
-use crate::fanbox::User;
+use crate::fanbox::{PostType, User};
 
 #[derive(Deserialize, Serialize, Debug, Clone, Hash)]
 #[serde(rename_all = "camelCase")]
@@ -9,6 +9,8 @@ pub struct PostListItem {
     pub id: String,
     pub title: String,
     pub fee_required: u32,
+    #[serde(rename = "type", default)]
+    pub ty: PostType,
     pub published_datetime: DateTime<Utc>,
     pub updated_datetime: DateTime<Utc>,
     pub tags: Vec<String>,
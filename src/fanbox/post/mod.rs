@@ -48,16 +48,52 @@ impl Post {
         self.title.clone()
     }
     pub fn published(&self) -> DateTime<Utc> {
-        self.published_datetime.clone()
+        self.published_datetime
     }
     pub fn updated(&self) -> DateTime<Utc> {
-        self.updated_datetime.clone()
+        self.updated_datetime
     }
     pub fn body(&self) -> PostBody {
         self.body.clone()
     }
 }
 
+/// A stub of a restricted post, from what the post list shows of it
+impl From<PostListItem> for Post {
+    fn from(item: PostListItem) -> Self {
+        let cover_image_url = item.cover.map(|cover| match cover {
+            Cover::CoverImage { url } | Cover::PostImage { url } => url,
+        });
+        let body = PostBody {
+            text: Some(item.excerpt.clone()),
+            ..Default::default()
+        };
+        Post {
+            id: item.id,
+            title: item.title,
+            fee_required: item.fee_required,
+            published_datetime: item.published_datetime,
+            updated_datetime: item.updated_datetime,
+            tags: item.tags,
+            is_liked: item.is_liked,
+            like_count: item.like_count,
+            comment_count: item.comment_count,
+            is_restricted: item.is_restricted,
+            user: item.user,
+            creator_id: item.creator_id,
+            has_adult_content: item.has_adult_content,
+            ty: item.ty,
+            cover_image_url,
+            body,
+            excerpt: item.excerpt,
+            next_post: None,
+            prev_post: None,
+            image_for_share: String::new(),
+            is_pinned: item.is_pinned,
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone, Hash)]
 #[serde(rename_all = "camelCase")]
 pub struct PostShort {
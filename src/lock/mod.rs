@@ -0,0 +1,76 @@
+use std::{
+    error::Error,
+    fs::{File, OpenOptions, TryLockError},
+    io::{Read, Seek, Write},
+    path::Path,
+    time::Duration,
+};
+
+use log::info;
+
+const LOCK_FILE: &str = ".fanbox-archive.lock";
+
+/// Exclusive lock on the output directory, held until dropped
+///
+/// The file lock is released by the OS even if the process crashes,
+/// the file only keeps the PID of the holder for error messages.
+#[derive(Debug)]
+pub struct ArchiveLock {
+    file: File,
+}
+
+impl ArchiveLock {
+    pub async fn acquire(output: &Path, wait: bool) -> Result<Self, Box<dyn Error>> {
+        let path = output.join(LOCK_FILE);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+
+        let mut waiting = false;
+        loop {
+            match file.try_lock() {
+                Ok(()) => break,
+                Err(TryLockError::WouldBlock) => {
+                    let pid = holder(&mut file);
+                    if !wait {
+                        return Err(format!(
+                            "Another run (PID {}) is using {}, pass `--wait-lock` to wait for it",
+                            pid,
+                            output.display()
+                        )
+                        .into());
+                    }
+                    if !waiting {
+                        info!("Waiting for another run (PID {}) to finish", pid);
+                        waiting = true;
+                    }
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+                Err(TryLockError::Error(e)) => return Err(e.into()),
+            }
+        }
+
+        file.set_len(0)?;
+        file.rewind()?;
+        write!(file, "{}", std::process::id())?;
+        Ok(Self { file })
+    }
+}
+
+impl Drop for ArchiveLock {
+    fn drop(&mut self) {
+        self.file.set_len(0).ok();
+        self.file.unlock().ok();
+    }
+}
+
+fn holder(file: &mut File) -> String {
+    let mut pid = String::new();
+    match file.rewind().and_then(|_| file.read_to_string(&mut pid)) {
+        Ok(_) if !pid.trim().is_empty() => pid.trim().to_string(),
+        _ => "unknown".to_string(),
+    }
+}
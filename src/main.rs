@@ -1,60 +1,319 @@
 mod api;
 mod config;
 mod creator;
+mod lock;
+mod notify;
 mod post;
+mod shutdown;
+mod storage;
+mod summary;
+mod verify;
 
 pub mod fanbox;
 
-use std::error::Error;
+use std::{
+    collections::HashMap,
+    error::Error,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
 use config::Config;
-use creator::{display_creators, get_creators, sync_creators};
-use log::info;
-use post::{filter_unsynced_posts, get_or_insert_tag, get_post_urls, get_posts, sync_posts};
+use creator::{
+    display_creators, get_creators, mark_archived, prune_creators, sort_creators, sync_creators,
+    SyncedCreator,
+};
+use lock::ArchiveLock;
+use log::{error, info, warn};
+use notify::notify;
+use post::{
+    filter_posts, filter_unsynced_posts, get_or_insert_tag, get_post_urls, get_posts,
+    get_posts_by_id, mark_deleted_posts, sync_posts,
+};
+use post_archiver::PostTagId;
 use rusqlite::Connection;
+use summary::{display_creator_summaries, display_run_report, CreatorSummary, RunReport};
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
+fn main() -> Result<(), Box<dyn Error>> {
     let config = Config::parse();
+    if let Some(command) = config.subcommand() {
+        return command.execute(&config);
+    }
+
+    let runtime = match config.threads() {
+        Some(1) => tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?,
+        Some(threads) => tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(threads)
+            .enable_all()
+            .build()?,
+        None => tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?,
+    };
+    runtime.block_on(run(config))
+}
+
+async fn run(config: Config) -> Result<(), Box<dyn Error>> {
     config.init_logger();
     info!("# Fanbox Archive #");
+    info!("User-Agent: {}", config.user_agent());
     info!("");
 
     let mut conn = create_connection(&config)?;
+    let lock = ArchiveLock::acquire(config.output(), config.wait_lock()).await?;
+    storage::prepare(&config)?;
+    shutdown::listen();
+
+    let exit_code = loop {
+        let started = Instant::now();
+        let exit_code = match archive(&config, &mut conn).await {
+            Ok(mut report) => {
+                report.elapsed = started.elapsed();
+                match report.failures() {
+                    _ if shutdown::stopping() => info!("Stopped"),
+                    0 => info!("All done!"),
+                    failures => warn!("Done with {} failures", failures),
+                }
+                display_run_report(&report);
+                if let Some(path) = config.report() {
+                    if let Err(e) = report.save(path) {
+                        warn!("Failed to write report {}: {}", path.display(), e);
+                    }
+                }
+                notify(&config, Ok(&report)).await;
+                report.exit_code()
+            }
+            Err(e) => {
+                notify(&config, Err(e.to_string())).await;
+                if config.watch().is_none() {
+                    return Err(e);
+                }
+                error!("Run failed: {}", e);
+                1
+            }
+        };
+
+        if shutdown::stopping() {
+            break exit_code;
+        }
+        let Some(interval) = config.watch() else {
+            break exit_code;
+        };
+        let delay = jitter(interval);
+        info!("Next run in {}m", delay.as_secs() / 60);
+        info!("");
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {},
+            _ = shutdown::wait() => {
+                info!("Stopped watching");
+                break exit_code;
+            },
+        }
+    };
+
+    drop(lock);
+    if shutdown::stopping() {
+        std::process::exit(shutdown::EXIT_CODE);
+    }
+    if exit_code != 0 {
+        std::process::exit(exit_code);
+    }
+    Ok(())
+}
+
+async fn archive(config: &Config, conn: &mut Connection) -> Result<RunReport, Box<dyn Error>> {
+    if !config.posts().is_empty() {
+        return archive_posts(config, conn).await;
+    }
 
     info!("Loading Creator List");
-    let creators = get_creators(&config).await?;
-    display_creators(&creators);
+    let creators = get_creators(config).await?;
+    display_creators(conn, &creators)?;
+    if config.prune_creators() {
+        prune_creators(config, conn).await?;
+    }
 
     info!("Syncing Creator List");
-    let creators = sync_creators(&mut conn, creators)?;
+    let mut creators = sync_creators(conn, creators)?;
+    sort_creators(conn, config, &mut creators)?;
 
     info!("Loading Creators Post");
-    let fanbox_tag = get_or_insert_tag(&mut conn,"fanbox")?;
-    let free_tag = get_or_insert_tag(&mut conn,"free")?;
-    for creator in creators {
-        info!("{}", creator.id());
-        let posts = get_post_urls(&config, creator.creator()).await?;
-        let posts = if config.force() {
-            info!("{} posts", posts.len());
-            posts
-        } else {
-            let total_post = posts.len();
-            let posts: Vec<fanbox::PostListItem> = filter_unsynced_posts(&mut conn, posts)?;
-            info!("{} posts, {} unsynced", total_post, posts.len());
-            posts
-        };
-
-        let posts = get_posts(&config, posts).await?;
-        if !posts.is_empty() {
-            sync_posts(&mut conn, &config, &creator, posts, (fanbox_tag,free_tag)).await?;
+    let fanbox_tag = get_or_insert_tag(conn, "fanbox")?;
+    let free_tag = get_or_insert_tag(conn, "free")?;
+    let mut report = RunReport::default();
+    let total = creators.len();
+    for (index, creator) in creators.into_iter().enumerate() {
+        if shutdown::stopping() {
+            info!("{} creators left for the next run", total - index);
+            break;
         }
+        info!(creator_id = creator.id().as_str(); "{}", creator.id());
+        match archive_creator(config, conn, &creator, (fanbox_tag, free_tag)).await {
+            Ok(summary) => {
+                // a creator cut short by a stop is still stale
+                if !shutdown::stopping() {
+                    mark_archived(conn, creator.author().id)?;
+                }
+                report.creators.push(summary);
+            }
+            Err(e) if config.fail_fast() => return Err(e),
+            Err(e) => {
+                error!(creator_id = creator.id().as_str(); "Failed to archive {}: {}", creator.id(), e);
+                report.failed_creators.push(creator.id().clone());
+            }
+        }
+        info!("");
+    }
 
+    display_creator_summaries(&report.creators);
+    if !report.failed_creators.is_empty() {
+        warn!(
+            "{} creators failed: {}",
+            report.failed_creators.len(),
+            report.failed_creators.join(", ")
+        );
+    }
+    Ok(report)
+}
+
+async fn archive_posts(
+    config: &Config,
+    conn: &mut Connection,
+) -> Result<RunReport, Box<dyn Error>> {
+    info!("Loading Posts");
+    let posts = get_posts_by_id(config, config.posts()).await;
+    let failed_posts = config
+        .posts()
+        .iter()
+        .filter(|id| !posts.iter().any(|post| &post.id == *id))
+        .cloned()
+        .collect();
+    let mut report = RunReport {
+        failed_posts,
+        ..Default::default()
+    };
+    if !report.failed_posts.is_empty() && config.fail_fast() {
+        return Err(format!("{} posts failed to load", report.failed_posts.len()).into());
+    }
+    info!("");
+
+    let mut grouped: HashMap<String, Vec<fanbox::Post>> = HashMap::new();
+    for post in posts {
+        grouped.entry(post.creator()).or_default().push(post);
+    }
+
+    let fanbox_tag = get_or_insert_tag(conn, "fanbox")?;
+    let free_tag = get_or_insert_tag(conn, "free")?;
+    for (creator_id, posts) in grouped {
+        if shutdown::stopping() {
+            break;
+        }
+        info!(creator_id = creator_id.as_str(); "{}", creator_id);
+        let started = Instant::now();
+        let mut summary = CreatorSummary::new(&creator_id);
+        let creator = fanbox::Creator::from(&posts[0]);
+        let creator = sync_creators(conn, vec![creator])?
+            .pop()
+            .expect("Failed to sync creator");
+        sync_posts(
+            conn,
+            config,
+            &creator,
+            posts,
+            (fanbox_tag, free_tag),
+            &mut summary,
+        )
+        .await?;
+        summary.elapsed = started.elapsed();
+        report.creators.push(summary);
         info!("");
     }
 
-    info!("All done!");
-    Ok(())
+    display_creator_summaries(&report.creators);
+    Ok(report)
+}
+
+async fn archive_creator(
+    config: &Config,
+    conn: &mut Connection,
+    creator: &SyncedCreator,
+    fanbox_and_free_tag: (PostTagId, PostTagId),
+) -> Result<CreatorSummary, Box<dyn Error>> {
+    let started = Instant::now();
+    let mut summary = CreatorSummary::new(creator.id());
+    let posts = get_post_urls(config, creator.creator()).await?;
+    summary.stages.list = started.elapsed();
+    // the post list is only complete when no page was skipped by `--since`
+    if config.force() && config.since().is_none() {
+        let deleted = mark_deleted_posts(conn, creator.id(), &posts)?;
+        if deleted != 0 {
+            info!("{} posts deleted from fanbox", deleted);
+        }
+    }
+    let listed = posts.len();
+    let posts = filter_posts(config, posts);
+    let posts = if config.force() {
+        info!("{} posts", posts.len());
+        posts
+    } else {
+        let total_post = posts.len();
+        let posts: Vec<fanbox::PostListItem> = filter_unsynced_posts(conn, posts)?;
+        info!("{} posts, {} unsynced", total_post, posts.len());
+        posts
+    };
+
+    summary.skipped_posts = listed - posts.len();
+    // restricted posts can't be fetched, only what the post list shows is archived
+    let (stubs, posts): (Vec<_>, Vec<_>) = posts.into_iter().partition(|post| post.is_restricted);
+    if !stubs.is_empty() {
+        info!("{} restricted posts archived as stubs", stubs.len());
+        let stubs = stubs.into_iter().map(fanbox::Post::from).collect();
+        sync_posts(
+            conn,
+            config,
+            creator,
+            stubs,
+            fanbox_and_free_tag,
+            &mut summary,
+        )
+        .await?;
+    }
+    // only keep a bounded number of full posts in memory
+    let total = posts.len();
+    for (index, posts) in posts.chunks(config.max_in_flight_posts()).enumerate() {
+        if shutdown::stopping() {
+            let done = index * config.max_in_flight_posts();
+            info!("{} posts left for the next run", total - done);
+            break;
+        }
+        let fetch_started = Instant::now();
+        let posts = get_posts(config, posts.to_vec(), &mut summary.failed_posts).await?;
+        summary.stages.fetch += fetch_started.elapsed();
+        if !posts.is_empty() {
+            sync_posts(
+                conn,
+                config,
+                creator,
+                posts,
+                fanbox_and_free_tag,
+                &mut summary,
+            )
+            .await?;
+        }
+    }
+
+    summary.elapsed = started.elapsed();
+    Ok(summary)
+}
+
+/// Add up to 10% of random delay, so instances started together drift apart
+fn jitter(interval: Duration) -> Duration {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos();
+    interval + interval.mul_f64(nanos as f64 / 1e9 / 10.0)
 }
 
 pub fn create_connection(config: &Config) -> Result<rusqlite::Connection, rusqlite::Error> {
@@ -74,4 +333,4 @@ pub fn create_connection(config: &Config) -> Result<rusqlite::Connection, rusqli
     };
 
     Ok(conn)
-}
\ No newline at end of file
+}
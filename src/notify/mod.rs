@@ -0,0 +1,91 @@
+use std::time::Duration;
+
+use log::{info, warn};
+use reqwest::Client;
+use serde_json::{json, Value};
+
+use crate::{
+    config::{notify_on::NotifyOn, Config},
+    summary::{format_bytes, RunReport},
+};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Post the outcome of a run to `--notify-webhook`, failures are only logged
+///
+/// Discord webhooks get an embed, any other url gets the raw report.
+pub async fn notify(config: &Config, outcome: Result<&RunReport, String>) {
+    let Some(url) = config.notify_webhook() else {
+        return;
+    };
+
+    let status = match &outcome {
+        Ok(report) if report.failures() == 0 => "success",
+        Ok(_) => "partial",
+        Err(_) => "failed",
+    };
+    if status == "success" && config.notify_on() == NotifyOn::Failure {
+        return;
+    }
+
+    let payload = if is_discord(url) {
+        discord_payload(status, &outcome)
+    } else {
+        match &outcome {
+            Ok(report) => json!({ "status": status, "report": report }),
+            Err(error) => json!({ "status": status, "error": error }),
+        }
+    };
+
+    let response = Client::new()
+        .post(url)
+        .timeout(TIMEOUT)
+        .json(&payload)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+    match response {
+        Ok(_) => info!("Notified webhook ({})", status),
+        Err(e) => warn!("Failed to notify webhook: {}", e),
+    }
+}
+
+fn is_discord(url: &str) -> bool {
+    ["discord.com/api/webhooks/", "discordapp.com/api/webhooks/"]
+        .iter()
+        .any(|prefix| url.contains(prefix))
+}
+
+fn discord_payload(status: &str, outcome: &Result<&RunReport, String>) -> Value {
+    let (color, description) = match outcome {
+        Ok(report) => {
+            let posts: usize = report.creators.iter().map(|c| c.posts).sum();
+            let files: usize = report.creators.iter().map(|c| c.files).sum();
+            let bytes: u64 = report.creators.iter().map(|c| c.bytes).sum();
+            let color = if status == "success" {
+                0x2ecc71
+            } else {
+                0xe67e22
+            };
+            let description = format!(
+                "{} creators, {} posts, {} files ({})\n{} failures in {:.0}s",
+                report.creators.len(),
+                posts,
+                files,
+                format_bytes(bytes),
+                report.failures(),
+                report.elapsed.as_secs_f64()
+            );
+            (color, description)
+        }
+        Err(error) => (0xe74c3c, error.clone()),
+    };
+
+    json!({
+        "embeds": [{
+            "title": format!("Fanbox Archive: {}", status),
+            "description": description,
+            "color": color,
+        }]
+    })
+}
@@ -1,17 +1,25 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    sync::Mutex,
+};
 
-use log::error;
-use post_archiver::{AuthorId, Content, FileMetaId, PostId};
+use log::warn;
+use post_archiver::{utils::get_mime, AuthorId, Content, PostId};
+use reqwest::Url;
 use serde_json::{json, Value};
 
 use crate::{
+    config::{content_format::ContentFormat, Config},
     fanbox::{
-        PostBlock, PostBlockStyle, PostBody, PostEmbed, PostFile, PostImage, PostTextEmbed,
-        PostVideo,
+        LinkPreview, Post, PostBlock, PostBlockLink, PostBlockStyle, PostBody, PostEmbed, PostFile,
+        PostImage, PostTextEmbed, PostVideo, Tweet,
     },
     post::get_source_link,
 };
 
+use super::html::{escape, Fragment};
+
 impl PostBody {
     pub fn files(&self, author: AuthorId, post: PostId) -> Vec<PostFileMeta> {
         let mut files: Vec<PostFileMeta> = vec![];
@@ -40,6 +48,29 @@ impl PostBody {
             ));
         };
 
+        for (id, preview) in self.link_previews.iter() {
+            files.extend(PostFileMeta::from_preview(id, preview, author, post));
+        }
+
+        for (id, tweet) in self.tweets.iter() {
+            let media = tweet.iter().flat_map(|tweet| tweet.media.iter());
+            for (index, url) in media.enumerate() {
+                files.push(PostFileMeta::from_tweet_media(id, index, url, author, post));
+            }
+        }
+
+        // names only differing in case collide on Windows and macOS
+        // `original_name` is kept for the sanitize rename only, the counted name is new
+        let mut taken = HashSet::new();
+        for file in files.iter_mut() {
+            let original = file.filename.clone();
+            let mut counter = 1;
+            while !taken.insert(file.filename.to_lowercase()) {
+                counter += 1;
+                file.filename = with_counter(&original, counter);
+            }
+        }
+
         // util function
         fn post_images_to_files(
             images: Vec<PostImage>,
@@ -65,223 +96,589 @@ impl PostBody {
 
         files
     }
-    pub fn content(&self, files: &HashMap<String, FileMetaId>) -> Vec<Content> {
-        let mut content = self.text(files);
+    pub fn content(&self, files: &HashMap<String, Content>, config: &Config) -> Vec<Content> {
+        let mut content = self.text(files, config);
+
+        // blocks already place their images, files and videos inline
+        let mut emitted: HashSet<&str> = self
+            .blocks
+            .iter()
+            .flatten()
+            .filter_map(|block| match block {
+                PostBlock::Image { image_id } => Some(image_id.as_str()),
+                PostBlock::File { file_id } => Some(file_id.as_str()),
+                PostBlock::Video { video_id } => Some(video_id.as_str()),
+                _ => None,
+            })
+            .collect();
 
-        for image in self.images.clone().unwrap_or_default() {
-            content.push(Content::File(*files.get(&image.id).unwrap()));
+        let images = self.images.iter().flatten();
+        let images = images.chain(self.image_map.iter().flat_map(|map| map.values()));
+        for image in images {
+            if emitted.insert(&image.id) {
+                content.push(file_or_missing(files, "image", &image.id));
+            }
         }
 
-        for file in self.files.clone().unwrap_or_default() {
-            content.push(Content::File(*files.get(&file.id).unwrap()));
+        let post_files = self.files.iter().flatten();
+        let post_files = post_files.chain(self.file_map.iter().flat_map(|map| map.values()));
+        for file in post_files {
+            if emitted.insert(&file.id) {
+                content.push(file_or_missing(files, "file", &file.id));
+            }
         }
 
-        for video in self.videos.clone().unwrap_or_default() {
-            content.push(Content::Text(video.to_text()));
+        for video in self.videos.iter().flatten() {
+            if emitted.insert(&video.video_id) {
+                content.push(Content::Text(video.to_text(config.content_format())));
+            }
         }
 
         content
     }
 
-    pub fn text(&self, files: &HashMap<String, FileMetaId>) -> Vec<Content> {
+    pub fn text(&self, files: &HashMap<String, Content>, config: &Config) -> Vec<Content> {
         let mut content = vec![];
         if let Some(text) = self.text.clone() {
-            content.push(Content::Text(text.replace("\n", "<br>")));
+            let text = match config.content_format() {
+                ContentFormat::Markdown => text.replace("\n", "<br>"),
+                ContentFormat::Html => format!("<p>{}</p>", escape(&text).replace("\n", "<br>")),
+            };
+            content.push(Content::Text(text));
         }
 
         if let Some(blocks) = self.blocks.as_ref() {
             for block in blocks.clone() {
-                content.push(block.to_text(self, &files));
+                let attachments = self.attachments(&block);
+                content.push(block.to_text(self, files, config));
+                content.extend(attachments.iter().filter_map(|id| files.get(id)).cloned());
             }
         }
 
         content
     }
+
+    /// Ids of the files archived for a block, placed right after it
+    fn attachments(&self, block: &PostBlock) -> Vec<String> {
+        match block {
+            PostBlock::UrlEmbed { url_embed_id } => vec![preview_id(url_embed_id)],
+            PostBlock::Embed { embed_id } => {
+                let media = self.tweets.get(embed_id).into_iter().flatten();
+                let media = media.flat_map(|tweet| 0..tweet.media.len());
+                media.map(|index| tweet_media_id(embed_id, index)).collect()
+            }
+            _ => vec![],
+        }
+    }
 }
 
 impl PostBlock {
-    pub fn to_text(self, body: &PostBody, files: &HashMap<String, FileMetaId>) -> Content {
+    pub fn to_text(
+        self,
+        body: &PostBody,
+        files: &HashMap<String, Content>,
+        config: &Config,
+    ) -> Content {
+        let format = config.content_format();
         match self {
-            PostBlock::P { text, styles } => {
+            PostBlock::P {
+                text,
+                styles,
+                links,
+            } => {
                 if text.is_empty() {
-                    Content::Text("<br>".to_string())
-                } else {
-                    Content::Text(Self::style_text(text, styles))
+                    return Content::Text("<br>".to_string());
+                }
+                let text = Self::style_text(text, styles, links, format);
+                match format {
+                    ContentFormat::Markdown => Content::Text(text),
+                    ContentFormat::Html => Content::Text(format!("<p>{}</p>", text)),
                 }
             }
-            PostBlock::Header { text, styles } => {
-                Content::Text(format!("# {}", Self::style_text(text, styles)))
+            PostBlock::Header {
+                text,
+                styles,
+                links,
+            } => {
+                let level = config.header_level();
+                let text = Self::style_text(text, styles, links, format);
+                match format {
+                    ContentFormat::Markdown => {
+                        Content::Text(format!("{} {}", "#".repeat(level as usize), text))
+                    }
+                    ContentFormat::Html => {
+                        Content::Text(format!("<h{}>{}</h{}>", level, text, level))
+                    }
+                }
             }
-            PostBlock::Image { image_id } => Content::File(*files.get(&image_id).unwrap()),
-            PostBlock::File { file_id } => Content::File(*files.get(&file_id).unwrap()),
+            PostBlock::Image { image_id } => file_or_missing(files, "image", &image_id),
+            PostBlock::File { file_id } => file_or_missing(files, "file", &file_id),
             PostBlock::Embed { embed_id } => {
-                let Some(embed) = body.embed_map.as_ref().unwrap().get(&embed_id) else {
+                let Some(embed) = body.embed_map.as_ref().and_then(|map| map.get(&embed_id)) else {
                     return Content::Text(format!("[Embed not found: {}]", embed_id));
                 };
-                Content::Text(embed.to_text())
+                match body.tweets.get(&embed_id) {
+                    Some(Some(tweet)) => Content::Text(tweet.to_text(format)),
+                    Some(None) => Content::Text(format!("{} [deleted]", embed.to_text(format))),
+                    None => Content::Text(embed.to_text(format)),
+                }
             }
             PostBlock::Video { video_id } => {
-                let videos = body.videos.as_ref().unwrap();
-                let video = videos.iter().find(|v| v.video_id == video_id).unwrap();
-                Content::Text(video.to_text())
+                let mut videos = body.videos.iter().flatten();
+                let Some(video) = videos.find(|v| v.video_id == video_id) else {
+                    warn!("Missing video {}", video_id);
+                    return Content::Text(format!("[missing video {}]", video_id));
+                };
+                Content::Text(video.to_text(format))
             }
             PostBlock::UrlEmbed { url_embed_id } => {
-                let Some(url_embed) = body.url_embed_map.as_ref().unwrap().get(&url_embed_id)
+                if let Some(preview) = body.link_previews.get(&url_embed_id) {
+                    return Content::Text(preview.to_text(format));
+                }
+                let Some(url_embed) = body
+                    .url_embed_map
+                    .as_ref()
+                    .and_then(|map| map.get(&url_embed_id))
                 else {
                     return Content::Text(format!("[URL Embed not found: {}]", url_embed_id));
                 };
-                Content::Text(url_embed.to_text())
+                Content::Text(url_embed.to_text(format))
+            }
+            PostBlock::HorizontalRule => match format {
+                ContentFormat::Markdown => Content::Text("---".to_string()),
+                ContentFormat::Html => Content::Text("<hr>".to_string()),
+            },
+            PostBlock::Unknown(block) => {
+                warn!("Unknown block type `{}`, keep it as a comment", block.ty);
+                Content::Text(format!(
+                    "<!-- unknown block: {} -->",
+                    block.raw.replace("--", "-&#45;")
+                ))
             }
         }
     }
 
-    pub fn style_text(text: String, styles: Option<Vec<PostBlockStyle>>) -> String {
-        let Some(mut styles) = styles else {
-            return text;
-        };
+    pub fn style_text(
+        text: String,
+        styles: Option<Vec<PostBlockStyle>>,
+        links: Option<Vec<PostBlockLink>>,
+        format: ContentFormat,
+    ) -> String {
+        if styles.is_none() && links.is_none() {
+            return plain(format, &text);
+        }
 
-        let mut insert_map: HashMap<usize, String> = HashMap::new();
-        styles.sort_by(|a, b| a.offset.cmp(&b.offset));
-        while let Some(style) = styles.pop() {
-            let offset = style.offset as usize;
-            let length = style.length as usize;
-            let (prefix, suffix) = match style.ty.as_str() {
-                "bold" => ("**", "**"),
-                _ => {
-                    error!("Unknown style: {:?}", style);
-                    unimplemented!()
+        let chars: Vec<char> = text.chars().collect();
+        let mut spans = vec![];
+        for link in links.unwrap_or_default() {
+            if format == ContentFormat::Html && !is_safe_url(&link.url) {
+                continue;
+            }
+            let start = utf16_to_char_index(&chars, link.offset as usize);
+            let end = utf16_to_char_index(&chars, (link.offset + link.length) as usize);
+            let (prefix, suffix) = match format {
+                ContentFormat::Markdown => ("[".to_string(), format!("]({})", link.url)),
+                ContentFormat::Html => (
+                    format!("<a href=\"{}\">", escape(&link.url)),
+                    "</a>".to_string(),
+                ),
+            };
+            spans.push(StyleSpan {
+                start,
+                end,
+                prefix,
+                suffix,
+                link: true,
+            });
+        }
+        for style in styles.unwrap_or_default() {
+            let (prefix, suffix) = match (style.ty.as_str(), format) {
+                ("bold", ContentFormat::Markdown) => ("**", "**"),
+                ("bold", ContentFormat::Html) => ("<b>", "</b>"),
+                ("italic", ContentFormat::Markdown) => ("*", "*"),
+                ("italic", ContentFormat::Html) => ("<i>", "</i>"),
+                ("underline", _) => ("<u>", "</u>"),
+                ("strike", ContentFormat::Markdown) => ("~~", "~~"),
+                ("strike", ContentFormat::Html) => ("<s>", "</s>"),
+                (ty, _) => {
+                    warn_unknown_style(ty);
+                    continue;
                 }
             };
-            let prefix_entry = insert_map.entry(offset).or_default();
-            *prefix_entry += prefix;
+            let start = utf16_to_char_index(&chars, style.offset as usize);
+            let end = utf16_to_char_index(&chars, (style.offset + style.length) as usize);
+            spans.push(StyleSpan {
+                start,
+                end,
+                prefix: prefix.to_string(),
+                suffix: suffix.to_string(),
+                link: false,
+            });
+        }
+        render_spans(&chars, spans, format)
+    }
+}
 
-            let suffix_entry = insert_map.entry(offset + length).or_default();
-            *suffix_entry = suffix.to_string() + suffix_entry;
+/// The excerpt of a post, quoted before its content
+///
+/// A stub already shows the excerpt as its text.
+pub fn excerpt(post: &Post, format: ContentFormat) -> Option<Content> {
+    let excerpt = post.excerpt.trim();
+    if excerpt.is_empty() || post.is_restricted {
+        return None;
+    }
+    let text = match format {
+        ContentFormat::Markdown => format!("> {}", excerpt.replace("\n", "<br>")),
+        ContentFormat::Html => format!(
+            "<blockquote>{}</blockquote>",
+            escape(excerpt).replace("\n", "<br>")
+        ),
+    };
+    Some(Content::Text(text))
+}
+
+/// Get the content of a file, or a placeholder when the post no longer has it
+fn file_or_missing(files: &HashMap<String, Content>, kind: &str, id: &str) -> Content {
+    match files.get(id) {
+        Some(content) => content.clone(),
+        None => {
+            warn!("Missing {} {}", kind, id);
+            Content::Text(format!("[missing {} {}]", kind, id))
+        }
+    }
+}
+
+/// A marked range of a text, in char indices
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct StyleSpan {
+    start: usize,
+    end: usize,
+    prefix: String,
+    suffix: String,
+    link: bool,
+}
+
+/// Convert an offset in UTF-16 code units, used by fanbox, into a char index
+/// example: offset `2` in `😀a` is the char index `1`
+fn utf16_to_char_index(chars: &[char], offset: usize) -> usize {
+    let mut units = 0;
+    for (index, char) in chars.iter().enumerate() {
+        if units >= offset {
+            return index;
+        }
+        units += char.len_utf16();
+    }
+    chars.len()
+}
+
+/// Insert the markers of spans into a text, always properly nested
+///
+/// Equal markers which touch or overlap are merged, a span crossing the end of
+/// another one is closed and opened again around it.
+/// example: bold `0..4` and strike `2..6` of `abcdef` -> `**ab~~cd~~**~~ef~~`
+fn render_spans(chars: &[char], mut spans: Vec<StyleSpan>, format: ContentFormat) -> String {
+    spans.retain(|span| span.start < span.end);
+    spans.sort_by(|a, b| (&a.prefix, &a.suffix, a.start).cmp(&(&b.prefix, &b.suffix, b.start)));
+    let mut merged: Vec<StyleSpan> = vec![];
+    for span in spans {
+        match merged.last_mut() {
+            Some(last)
+                if (&last.prefix, &last.suffix) == (&span.prefix, &span.suffix)
+                    && span.start <= last.end =>
+            {
+                last.end = last.end.max(span.end);
+            }
+            _ => merged.push(span),
         }
-        // Insert the styles in reverse order to avoid messing up the offsets.
-        let mut output = String::new();
-        for (i, char) in text.chars().enumerate() {
-            if let Some(insert) = insert_map.get(&i) {
-                output += insert;
+    }
+    // links go outside of the styles sharing their range
+    merged.sort_by_key(|span| !span.link);
+    let spans = merged;
+
+    let mut bounds: Vec<usize> = spans
+        .iter()
+        .flat_map(|span| [span.start, span.end])
+        .chain([0, chars.len()])
+        .collect();
+    bounds.sort();
+    bounds.dedup();
+
+    let mut output = String::new();
+    let mut open: Vec<usize> = vec![];
+    for window in bounds.windows(2) {
+        let (start, end) = (window[0], window[1]);
+        let active = |index: &usize| spans[*index].start <= start && spans[*index].end >= end;
+
+        // close everything opened after the first span which ends here
+        if let Some(position) = open.iter().position(|index| !active(index)) {
+            for index in open.drain(position..).rev() {
+                output += &spans[index].suffix;
             }
-            output.push(char);
         }
-        output
+        // the longest spans go outside
+        let mut opening: Vec<usize> = (0..spans.len())
+            .filter(|index| active(index) && !open.contains(index))
+            .collect();
+        opening.sort_by_key(|index| std::cmp::Reverse(spans[*index].end));
+        for index in opening {
+            output += &spans[index].prefix;
+            open.push(index);
+        }
+
+        output += &plain(format, &chars[start..end].iter().collect::<String>());
+    }
+    for index in open.into_iter().rev() {
+        output += &spans[index].suffix;
+    }
+    output
+}
+
+/// Warn about an unknown style once per type, its text is kept unstyled
+fn warn_unknown_style(ty: &str) {
+    static WARNED: Mutex<Option<HashSet<String>>> = Mutex::new(None);
+    let mut warned = WARNED.lock().unwrap();
+    if warned
+        .get_or_insert_with(HashSet::new)
+        .insert(ty.to_string())
+    {
+        warn!("Unknown text style `{}`, keep the text unstyled", ty);
     }
 }
 
 impl PostVideo {
-    pub fn to_text(&self) -> String {
+    pub fn to_text(&self, format: ContentFormat) -> String {
         match self.service_provider.as_str() {
-            "youtube" => {
-                format!("[![youtube](https://img.youtube.com/vi/{}/0.jpg)](https://www.youtube.com/watch?v={})",self.video_id, self.video_id)
-            }
-            _ => {
-                error!("Unknown video provider ({})", self.service_provider);
-                error!("video_id: {}", self.video_id);
-                unimplemented!()
+            "youtube" => youtube(format, &self.video_id),
+            "vimeo" => link(
+                format,
+                "Vimeo",
+                &format!("https://vimeo.com/{}", self.video_id),
+            ),
+            provider => {
+                warn!("Unknown video provider `{}` ({})", provider, self.video_id);
+                plain(format, &format!("[Video: {} {}]", provider, self.video_id))
             }
         }
     }
 }
 
 impl PostEmbed {
-    pub fn to_text(&self) -> String {
+    pub fn to_text(&self, format: ContentFormat) -> String {
         match self.service_provider.as_str() {
-            "youtube" => {
-                format!("[![youtube](https://img.youtube.com/vi/{}/0.jpg)](https://www.youtube.com/watch?v={})",self.content_id, self.content_id)
-            }
-            "google_forms" => {
-                format!(
-                    "[Google Form](https://docs.google.com/forms/d/e/{}/viewform)",
+            "youtube" => youtube(format, &self.content_id),
+            "google_forms" => link(
+                format,
+                "Google Form",
+                &format!(
+                    "https://docs.google.com/forms/d/e/{}/viewform",
                     self.content_id
-                )
-            }
-            "fanbox" => {
-                fn deconstruct(input: &str) -> Result<(String, String), &'static str> {
-                    let parts: Vec<&str> = input.split('/').collect();
-                    if parts.len() == 4 && parts[0] == "creator" && parts[2] == "post" {
-                        let creator = parts[1].to_string();
-                        let post = parts[3].to_string();
-                        Ok((creator, post))
-                    } else {
-                        Err("The input string does not match the expected format.")
-                    }
+                ),
+            ),
+            "fanbox" => match fanbox_post_link(&self.content_id) {
+                Some((name, url)) => link(format, &format!("Fanbox Post ({})", name), &url),
+                None => {
+                    warn!("Unknown fanbox embed `{}`", self.content_id);
+                    plain(format, &format!("[Fanbox Post {}]", self.content_id))
                 }
-
-                let (creator, post) = deconstruct(&self.content_id).unwrap();
-                format!(
-                    "[Fanbox Post ({}/{})]({})",
-                    creator,
-                    post,
-                    get_source_link(&creator, &post)
-                )
-            }
-            "twitter" => {
-                format!(
-                    "[Tweet](https://twitter.com/i/web/status/{})",
-                    self.content_id
+            },
+            "twitter" => link(
+                format,
+                "Tweet",
+                &format!("https://twitter.com/i/web/status/{}", self.content_id),
+            ),
+            "soundcloud" => link(
+                format,
+                "SoundCloud",
+                &format!("https://soundcloud.com/{}", self.content_id),
+            ),
+            "vimeo" => link(
+                format,
+                "Vimeo",
+                &format!("https://vimeo.com/{}", self.content_id),
+            ),
+            "pixiv" => {
+                // the content id may be prefixed, e.g. `illust/12345`
+                let artwork = self.content_id.rsplit('/').next().unwrap_or_default();
+                link(
+                    format,
+                    &format!("pixiv Artwork {}", artwork),
+                    &format!("https://www.pixiv.net/artworks/{}", artwork),
                 )
             }
             provider => {
-                error!("Unknown embed provider ({})", provider);
-                error!("id: {}", self.id);
-                error!("content_id: {}", self.content_id);
-                unimplemented!()
+                warn!(
+                    "Unknown embed provider `{}` ({}: {})",
+                    provider, self.id, self.content_id
+                );
+                plain(
+                    format,
+                    &format!("[Embed: {} {}]", provider, self.content_id),
+                )
             }
         }
     }
 }
 
-impl PostTextEmbed {
-    pub fn to_text(&self) -> String {
-        match self {
-            PostTextEmbed::Html { id: _, html } => {
-                let Some(start) = html.find("<iframe src=\"") else {
-                    return "[Invalid URL Embed]".to_string();
-                };
-                let mut src = html.split_at(start + 13).1;
-                let Some(end) = src.find("\"") else {
-                    return "[Invalid URL Embed]".to_string();
-                };
-                src = src.split_at(end).0;
+/// Render a youtube video as its thumbnail linking to it
+fn youtube(format: ContentFormat, id: &str) -> String {
+    let thumbnail = format!("https://img.youtube.com/vi/{}/0.jpg", id);
+    let url = format!("https://www.youtube.com/watch?v={}", id);
+    match format {
+        ContentFormat::Markdown => format!("[![youtube]({})]({})", thumbnail, url),
+        ContentFormat::Html => format!(
+            "<a href=\"{}\"><img src=\"{}\" alt=\"youtube\"></a>",
+            escape(&url),
+            escape(&thumbnail)
+        ),
+    }
+}
 
-                format!("[{}]({})", src, src)
-            }
-            PostTextEmbed::HtmlCard { id: _, html } => {
-                let Some(start) = html.find("<iframe src=\"") else {
-                    return "[Invalid URL Embed]".to_string();
-                };
-                let mut src = html.split_at(start + 13).1;
+/// Render a link, only web and mail links are kept as such in HTML
+fn link(format: ContentFormat, text: &str, url: &str) -> String {
+    match format {
+        ContentFormat::Markdown => format!("[{}]({})", text, url),
+        ContentFormat::Html if is_safe_url(url) => {
+            format!("<a href=\"{}\">{}</a>", escape(url), escape(text))
+        }
+        ContentFormat::Html => escape(text),
+    }
+}
 
-                let Some(end) = src.find("\"") else {
-                    return "[Invalid URL Embed]".to_string();
-                };
-                src = src.split_at(end).0;
+/// Render a text without any markup
+fn plain(format: ContentFormat, text: &str) -> String {
+    match format {
+        ContentFormat::Markdown => text.to_string(),
+        ContentFormat::Html => escape(text),
+    }
+}
+
+fn is_safe_url(url: &str) -> bool {
+    ["https://", "http://", "mailto:"].iter().any(|scheme| {
+        url.get(..scheme.len())
+            .is_some_and(|s| s.eq_ignore_ascii_case(scheme))
+    })
+}
+
+/// Get the name and link of an embedded fanbox post from its content id
+///
+/// - `creator/xxx/post/123` -> `https://xxx.fanbox.cc/posts/123`
+/// - `post/123` -> `https://www.fanbox.cc/posts/123`
+/// - `https://official.fanbox.cc/posts/123` -> itself
+fn fanbox_post_link(content_id: &str) -> Option<(String, String)> {
+    let path = content_id
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_matches('/');
+    let parts: Vec<&str> = path.split('/').collect();
+    let (creator, post) = match parts.as_slice() {
+        ["creator", creator, "post", post] => (Some(*creator), *post),
+        ["post", post] => (None, *post),
+        [host, "posts", post] => (host.strip_suffix(".fanbox.cc"), *post),
+        _ => return None,
+    };
+    if post.is_empty() || !post.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
 
-                format!("[{}]({})", src, src)
+    Some(match creator {
+        Some(creator) => (
+            format!("{}/{}", creator, post),
+            get_source_link(creator, post),
+        ),
+        None => (
+            post.to_string(),
+            format!("https://www.fanbox.cc/posts/{}", post),
+        ),
+    })
+}
+
+impl LinkPreview {
+    pub fn to_text(&self, format: ContentFormat) -> String {
+        let title = match format {
+            ContentFormat::Markdown => format!("**{}**", link(format, &self.title, &self.url)),
+            ContentFormat::Html => format!("<b>{}</b>", link(format, &self.title, &self.url)),
+        };
+        let description = self.description.as_deref().map(|d| plain(format, d));
+        match (format, description) {
+            (ContentFormat::Markdown, Some(description)) => {
+                format!("> {}<br>\n> {}", title, description)
             }
-            PostTextEmbed::FanboxPost { id: _id, post_info } => {
-                format!(
-                    "[Fanbox Post {}]({})",
-                    post_info.title,
-                    get_source_link(&post_info.creator_id, &post_info.id)
-                )
+            (ContentFormat::Markdown, None) => format!("> {}", title),
+            (ContentFormat::Html, Some(description)) => {
+                format!("<blockquote>{}<br>{}</blockquote>", title, description)
             }
-            PostTextEmbed::FanboxCreator { id: _, profile } => {
-                format!(
-                    "[Creator {}](https://{}.fanbox.cc)",
-                    profile.name(),
-                    profile.creator_id()
-                )
+            (ContentFormat::Html, None) => format!("<blockquote>{}</blockquote>", title),
+        }
+    }
+}
+
+impl Tweet {
+    pub fn to_text(&self, format: ContentFormat) -> String {
+        let author = format!("— {} (@{})", self.name, self.screen_name);
+        let author = format!(
+            "{} {}",
+            plain(format, &author),
+            link(format, "Tweet", &self.url)
+        );
+        match format {
+            ContentFormat::Markdown => {
+                let text = self.text.replace("\n", "<br>\n> ");
+                format!("> {}<br>\n> {}", text, author)
+            }
+            ContentFormat::Html => {
+                let text = escape(&self.text).replace("\n", "<br>");
+                format!("<blockquote>{}<br>{}</blockquote>", text, author)
+            }
+        }
+    }
+}
+
+/// Id of a photo or video of a tweet, kept apart from the ids of fanbox files
+fn tweet_media_id(embed_id: &str, index: usize) -> String {
+    format!("tweet-{}-{}", embed_id, index)
+}
+
+/// Id of the cover of a restricted post
+pub const COVER_ID: &str = "cover";
+
+/// Id of the image shown when a post is shared, sometimes the only preview left of a deleted post
+pub const SHARE_IMAGE_ID: &str = "share";
+
+/// Id of the image of a link preview, kept apart from the ids of fanbox files
+fn preview_id(url_embed_id: &str) -> String {
+    format!("preview-{}", url_embed_id)
+}
+
+impl PostTextEmbed {
+    pub fn to_text(&self, format: ContentFormat) -> String {
+        match self {
+            PostTextEmbed::Html { id: _, html } | PostTextEmbed::HtmlCard { id: _, html } => {
+                let fragment = Fragment::parse(html);
+                let url = fragment
+                    .find("iframe", "src")
+                    .or_else(|| fragment.find("a", "href"));
+                match url {
+                    Some(url) if fragment.text.is_empty() => link(format, url, url),
+                    Some(url) => link(format, &fragment.text, url),
+                    None if fragment.text.is_empty() => plain(format, "[Invalid URL Embed]"),
+                    None => plain(format, &fragment.text),
+                }
             }
+            PostTextEmbed::FanboxPost { id: _id, post_info } => link(
+                format,
+                &format!("Fanbox Post {}", post_info.title),
+                &get_source_link(&post_info.creator_id, &post_info.id),
+            ),
+            PostTextEmbed::FanboxCreator { id: _, profile } => link(
+                format,
+                &format!("Creator {}", profile.name()),
+                &format!("https://{}.fanbox.cc", profile.creator_id()),
+            ),
             PostTextEmbed::Default {
                 id: _,
                 url,
                 host: _,
-            } => {
-                format!("[{}]({})", url, url)
-            }
+            } => link(format, url, url),
         }
     }
 }
@@ -294,6 +691,7 @@ pub struct PostFileMeta {
     pub post: PostId,
     pub url: String,
     pub mime: String,
+    pub size: Option<u64>,
     pub extra: Value,
 }
 
@@ -315,14 +713,22 @@ impl PostFileMeta {
             post,
             url,
             mime,
+            size: None,
             extra,
         }
     }
     pub fn from_file(file: PostFile, author: AuthorId, post: PostId) -> Self {
         let id = file.id();
-        let filename = file.filename();
+        let original = file.filename();
+        let filename = sanitize_filename(&original);
         let url = file.url();
         let mime = file.mime();
+        let size = Some(file.size());
+        let extra = if filename != original {
+            json!({ "original_name": original })
+        } else {
+            json!({})
+        };
 
         Self {
             id,
@@ -331,7 +737,584 @@ impl PostFileMeta {
             post,
             url,
             mime,
-            extra: Default::default(),
+            size,
+            extra,
+        }
+    }
+}
+
+impl PostFileMeta {
+    pub fn from_preview(
+        url_embed_id: &str,
+        preview: &LinkPreview,
+        author: AuthorId,
+        post: PostId,
+    ) -> Option<Self> {
+        let url = preview.image.clone()?;
+        let id = preview_id(url_embed_id);
+        let extension = url_extension(&url);
+        let filename = sanitize_filename(&format!("{}.{}", id, extension));
+        let mime = get_mime(&filename);
+        let extra = json!({ "preview": preview.url });
+
+        Some(Self {
+            id,
+            filename,
+            author,
+            post,
+            url,
+            mime,
+            size: None,
+            extra,
+        })
+    }
+
+    pub fn from_cover(source: &Post, author: AuthorId, post: PostId) -> Option<Self> {
+        let url = source.cover_image_url.clone()?;
+        let id = COVER_ID.to_string();
+        let filename = format!("{}.{}", id, url_extension(&url));
+        let mime = get_mime(&filename);
+
+        Some(Self {
+            id,
+            filename,
+            author,
+            post,
+            url,
+            mime,
+            size: None,
+            extra: json!({}),
+        })
+    }
+
+    pub fn from_share_image(source: &Post, author: AuthorId, post: PostId) -> Option<Self> {
+        let url = Some(source.image_for_share.clone()).filter(|url| !url.is_empty())?;
+        let id = SHARE_IMAGE_ID.to_string();
+        let filename = format!("{}.{}", id, url_extension(&url));
+        let mime = get_mime(&filename);
+
+        Some(Self {
+            id,
+            filename,
+            author,
+            post,
+            url,
+            mime,
+            size: None,
+            extra: json!({}),
+        })
+    }
+
+    pub fn from_tweet_media(
+        embed_id: &str,
+        index: usize,
+        url: &str,
+        author: AuthorId,
+        post: PostId,
+    ) -> Self {
+        let id = tweet_media_id(embed_id, index);
+        let filename = sanitize_filename(&format!("{}.{}", id, url_extension(url)));
+        let mime = get_mime(&filename);
+        let extra = json!({ "tweet": embed_id });
+
+        Self {
+            id,
+            filename,
+            author,
+            post,
+            url: url.to_string(),
+            mime,
+            size: None,
+            extra,
+        }
+    }
+}
+
+/// Get the extension of the file an url points to, `jpg` if it has none
+/// example: `https://pbs.twimg.com/media/abc.png?name=orig` -> `png`
+fn url_extension(url: &str) -> String {
+    let name = Url::parse(url)
+        .ok()
+        .and_then(|url| url.path_segments()?.next_back().map(str::to_string))
+        .unwrap_or_default();
+    name.rsplit_once('.')
+        .map(|(_, extension)| extension)
+        .filter(|extension| {
+            extension.len() <= 5 && extension.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+        .unwrap_or("jpg")
+        .to_lowercase()
+}
+
+/// Longest filename in bytes, leaving room below the usual 255 byte limit
+const MAX_FILENAME_BYTES: usize = 200;
+
+/// Make a creator provided filename safe on Windows and exFAT
+/// example: `What?.png` -> `What_.png`
+///
+/// Illegal characters are replaced, trailing dots and spaces trimmed, reserved
+/// names prefixed and long names cut while keeping the extension.
+pub fn sanitize_filename(filename: &str) -> String {
+    let replaced: String = filename
+        .chars()
+        .map(|char| match char {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            char if char.is_control() => '_',
+            char => char,
+        })
+        .collect();
+
+    let path = Path::new(&replaced);
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_string())
+        .filter(|ext| !ext.is_empty() && ext.len() <= 16 && !ext.ends_with(' '));
+    let stem = match &extension {
+        Some(extension) => &replaced[..replaced.len() - extension.len() - 1],
+        None => replaced.as_str(),
+    };
+
+    let mut stem = stem.trim_matches(' ').trim_end_matches('.').to_string();
+    if stem.is_empty() {
+        stem = "_".to_string();
+    }
+    let reserved = ["CON", "PRN", "AUX", "NUL"];
+    let upper = stem.to_uppercase();
+    let device = upper.strip_prefix("COM").or(upper.strip_prefix("LPT"));
+    if reserved.contains(&upper.as_str())
+        || device.is_some_and(|n| n.len() == 1 && n.chars().all(|c| c.is_ascii_digit()))
+    {
+        stem.insert(0, '_');
+    }
+
+    let suffix = extension.map_or(String::new(), |extension| format!(".{}", extension));
+    let mut limit = MAX_FILENAME_BYTES.saturating_sub(suffix.len());
+    if stem.len() > limit {
+        while !stem.is_char_boundary(limit) {
+            limit -= 1;
         }
+        stem.truncate(limit);
+        stem = stem.trim_end_matches([' ', '.']).to_string();
+    }
+    format!("{}{}", stem, suffix)
+}
+
+/// Number a filename which is already used in the post
+/// example: `image.png` -> `image (2).png`
+fn with_counter(filename: &str, counter: usize) -> String {
+    let path = Path::new(filename);
+    match (path.file_stem(), path.extension()) {
+        (Some(stem), Some(extension)) => format!(
+            "{} ({}).{}",
+            stem.to_string_lossy(),
+            counter,
+            extension.to_string_lossy()
+        ),
+        _ => format!("{} ({})", filename, counter),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use post_archiver::FileMetaId;
+
+    fn attachment(id: &str, name: &str, extension: &str) -> PostFile {
+        PostFile {
+            id: id.to_string(),
+            name: name.to_string(),
+            extension: extension.to_string(),
+            size: 1,
+            url: format!(
+                "https://downloads.fanbox.cc/files/post/1/{}.{}",
+                id, extension
+            ),
+        }
+    }
+
+    fn style(ty: &str, offset: u32, length: u32) -> PostBlockStyle {
+        PostBlockStyle {
+            ty: ty.to_string(),
+            offset,
+            length,
+        }
+    }
+
+    fn markdown(text: &str, styles: Vec<PostBlockStyle>) -> String {
+        PostBlock::style_text(
+            text.to_string(),
+            Some(styles),
+            None,
+            ContentFormat::Markdown,
+        )
+    }
+
+    #[test]
+    fn style_text_nests_overlapping_styles() {
+        let styles = vec![style("bold", 0, 4), style("strike", 2, 4)];
+        assert_eq!(markdown("abcdef", styles), "**ab~~cd~~**~~ef~~");
+
+        let styles = vec![style("bold", 0, 6), style("italic", 2, 2)];
+        assert_eq!(markdown("abcdef", styles), "**ab*cd*ef**");
+    }
+
+    #[test]
+    fn style_text_counts_offsets_in_utf16() {
+        let styles = vec![style("bold", 3, 3)];
+        assert_eq!(markdown("日本語テキスト", styles), "日本語**テキス**ト");
+
+        // an emoji is two UTF-16 code units
+        let styles = vec![style("bold", 2, 2)];
+        assert_eq!(markdown("😀ab", styles), "😀**ab**");
+    }
+
+    #[test]
+    fn style_text_keeps_links_outside_and_escapes_html() {
+        let links = vec![PostBlockLink {
+            offset: 0,
+            length: 3,
+            url: "https://example.com".to_string(),
+        }];
+        let text = PostBlock::style_text(
+            "a<b".to_string(),
+            Some(vec![style("bold", 0, 3)]),
+            Some(links.clone()),
+            ContentFormat::Html,
+        );
+        assert_eq!(text, "<a href=\"https://example.com\"><b>a&lt;b</b></a>");
+        let text = PostBlock::style_text(
+            "abc".to_string(),
+            Some(vec![style("bold", 0, 3)]),
+            Some(links),
+            ContentFormat::Markdown,
+        );
+        assert_eq!(text, "[**abc**](https://example.com)");
+    }
+
+    #[test]
+    fn style_text_ignores_unknown_styles() {
+        let styles = vec![style("sparkle", 0, 3), style("bold", 3, 3)];
+        assert_eq!(markdown("abcdef", styles), "abc**def**");
+    }
+
+    #[test]
+    fn utf16_to_char_index_skips_surrogate_pairs() {
+        let chars: Vec<char> = "😀a👍🏻b".chars().collect();
+        assert_eq!(utf16_to_char_index(&chars, 0), 0);
+        assert_eq!(utf16_to_char_index(&chars, 2), 1);
+        assert_eq!(utf16_to_char_index(&chars, 3), 2);
+        // `👍🏻` is two chars of two code units each
+        assert_eq!(utf16_to_char_index(&chars, 7), 4);
+        assert_eq!(utf16_to_char_index(&chars, 100), chars.len());
+    }
+
+    #[test]
+    fn render_spans_with_emoji() {
+        let styles = vec![style("bold", 0, 2), style("italic", 3, 3)];
+        assert_eq!(markdown("😀a👍b", styles), "**😀**a*👍b*");
+    }
+
+    #[test]
+    fn render_spans_with_cjk() {
+        let styles = vec![style("bold", 0, 2), style("strike", 1, 3)];
+        assert_eq!(markdown("日本語です", styles), "**日~~本~~**~~語で~~す");
+    }
+
+    #[test]
+    fn render_spans_merges_adjacent_bold_ranges() {
+        let styles = vec![style("bold", 0, 2), style("bold", 2, 2)];
+        assert_eq!(markdown("abcdef", styles), "**abcd**ef");
+
+        // overlapping too, in any order
+        let styles = vec![style("bold", 2, 3), style("bold", 0, 3)];
+        assert_eq!(markdown("abcdef", styles), "**abcde**f");
+
+        // but not ranges with a gap
+        let styles = vec![style("bold", 0, 2), style("bold", 3, 2)];
+        assert_eq!(markdown("abcdef", styles), "**ab**c**de**f");
+    }
+
+    fn video(provider: &str, id: &str) -> String {
+        let video = PostVideo {
+            service_provider: provider.to_string(),
+            video_id: id.to_string(),
+        };
+        video.to_text(ContentFormat::Markdown)
+    }
+
+    fn embed(provider: &str, content_id: &str) -> String {
+        let embed = PostEmbed {
+            id: "embed".to_string(),
+            service_provider: provider.to_string(),
+            content_id: content_id.to_string(),
+        };
+        embed.to_text(ContentFormat::Markdown)
+    }
+
+    #[test]
+    fn video_youtube() {
+        assert_eq!(
+            video("youtube", "abc"),
+            "[![youtube](https://img.youtube.com/vi/abc/0.jpg)](https://www.youtube.com/watch?v=abc)"
+        );
+    }
+
+    #[test]
+    fn video_vimeo() {
+        assert_eq!(video("vimeo", "123"), "[Vimeo](https://vimeo.com/123)");
+    }
+
+    #[test]
+    fn video_unknown_provider() {
+        assert_eq!(video("niconico", "sm9"), "[Video: niconico sm9]");
+    }
+
+    #[test]
+    fn embed_youtube() {
+        assert_eq!(
+            embed("youtube", "abc"),
+            "[![youtube](https://img.youtube.com/vi/abc/0.jpg)](https://www.youtube.com/watch?v=abc)"
+        );
+    }
+
+    #[test]
+    fn embed_google_forms() {
+        assert_eq!(
+            embed("google_forms", "1FAIpQL"),
+            "[Google Form](https://docs.google.com/forms/d/e/1FAIpQL/viewform)"
+        );
+    }
+
+    #[test]
+    fn embed_fanbox() {
+        assert_eq!(
+            embed("fanbox", "creator/xxx/post/123"),
+            "[Fanbox Post (xxx/123)](https://xxx.fanbox.cc/posts/123)"
+        );
+        assert_eq!(embed("fanbox", "creator/xxx"), "[Fanbox Post creator/xxx]");
+    }
+
+    #[test]
+    fn embed_twitter() {
+        assert_eq!(
+            embed("twitter", "123"),
+            "[Tweet](https://twitter.com/i/web/status/123)"
+        );
+    }
+
+    #[test]
+    fn embed_soundcloud() {
+        assert_eq!(
+            embed("soundcloud", "artist/track"),
+            "[SoundCloud](https://soundcloud.com/artist/track)"
+        );
+    }
+
+    #[test]
+    fn embed_vimeo() {
+        assert_eq!(embed("vimeo", "123"), "[Vimeo](https://vimeo.com/123)");
+    }
+
+    #[test]
+    fn embed_pixiv() {
+        let expected = "[pixiv Artwork 123](https://www.pixiv.net/artworks/123)";
+        assert_eq!(embed("pixiv", "123"), expected);
+        assert_eq!(embed("pixiv", "illust/123"), expected);
+    }
+
+    #[test]
+    fn embed_unknown_provider() {
+        assert_eq!(embed("bandcamp", "album"), "[Embed: bandcamp album]");
+    }
+
+    #[test]
+    fn fanbox_post_link_of_post_id() {
+        assert_eq!(
+            fanbox_post_link("post/12345"),
+            Some((
+                "12345".to_string(),
+                "https://www.fanbox.cc/posts/12345".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn fanbox_post_link_of_creator_post() {
+        assert_eq!(
+            fanbox_post_link("creator/x/post/1"),
+            Some(("x/1".to_string(), "https://x.fanbox.cc/posts/1".to_string()))
+        );
+    }
+
+    #[test]
+    fn fanbox_post_link_of_url() {
+        assert_eq!(
+            fanbox_post_link("https://official.fanbox.cc/posts/123"),
+            Some((
+                "official/123".to_string(),
+                "https://official.fanbox.cc/posts/123".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn fanbox_post_link_rejects_malformed_ids() {
+        assert_eq!(fanbox_post_link(""), None);
+        assert_eq!(fanbox_post_link("post/"), None);
+        assert_eq!(fanbox_post_link("post/abc"), None);
+        assert_eq!(fanbox_post_link("creator/x"), None);
+        assert_eq!(fanbox_post_link("creator/x/post/1/extra"), None);
+        assert_eq!(fanbox_post_link("https://example.com/other/1"), None);
+    }
+
+    fn image(id: &str) -> PostImage {
+        PostImage {
+            id: id.to_string(),
+            extension: "png".to_string(),
+            width: 1,
+            height: 1,
+            original_url: format!("https://downloads.fanbox.cc/images/post/1/{}.png", id),
+            thumbnail_url: String::new(),
+        }
+    }
+
+    /// Render the content of a body, with the files numbered in the given order
+    fn render(body: &PostBody, ids: &[&str]) -> Value {
+        let files = ids
+            .iter()
+            .enumerate()
+            .map(|(index, id)| {
+                (
+                    id.to_string(),
+                    Content::File(FileMetaId::new(index as u32 + 1)),
+                )
+            })
+            .collect();
+        serde_json::to_value(body.content(&files, &Config::default())).unwrap()
+    }
+
+    #[test]
+    fn content_of_article_post() {
+        let body = PostBody {
+            blocks: Some(vec![
+                PostBlock::P {
+                    text: "hello".to_string(),
+                    styles: None,
+                    links: None,
+                },
+                PostBlock::Image {
+                    image_id: "i1".to_string(),
+                },
+                PostBlock::P {
+                    text: String::new(),
+                    styles: None,
+                    links: None,
+                },
+                PostBlock::File {
+                    file_id: "f1".to_string(),
+                },
+            ]),
+            image_map: Some([("i1".to_string(), image("i1"))].into()),
+            file_map: Some([("f1".to_string(), attachment("f1", "notes", "txt"))].into()),
+            ..Default::default()
+        };
+        // the image and file placed by blocks are not appended again
+        assert_eq!(render(&body, &["i1", "f1"]), json!(["hello", 1, "<br>", 2]));
+    }
+
+    #[test]
+    fn content_of_image_post() {
+        let body = PostBody {
+            text: Some("caption\nline".to_string()),
+            images: Some(vec![image("i1"), image("i2")]),
+            ..Default::default()
+        };
+        assert_eq!(
+            render(&body, &["i1", "i2"]),
+            json!(["caption<br>line", 1, 2])
+        );
+    }
+
+    #[test]
+    fn content_of_file_post() {
+        let body = PostBody {
+            text: Some("download".to_string()),
+            files: Some(vec![attachment("f1", "pack", "zip")]),
+            ..Default::default()
+        };
+        assert_eq!(render(&body, &["f1"]), json!(["download", 1]));
+    }
+
+    #[test]
+    fn content_keeps_missing_files_visible() {
+        let body = PostBody {
+            images: Some(vec![image("i1")]),
+            ..Default::default()
+        };
+        assert_eq!(render(&body, &[]), json!(["[missing image i1]"]));
+    }
+
+    #[test]
+    fn sanitize_filename_keeps_emoji_and_full_width_colon() {
+        assert_eq!(sanitize_filename("🎨 art.png"), "🎨 art.png");
+        assert_eq!(sanitize_filename("第1話：前編.pdf"), "第1話：前編.pdf");
+    }
+
+    #[test]
+    fn sanitize_filename_replaces_reserved_characters() {
+        assert_eq!(sanitize_filename("What?.png"), "What_.png");
+        assert_eq!(sanitize_filename("a:b/c\\d.zip"), "a_b_c_d.zip");
+        assert_eq!(sanitize_filename("CON.txt"), "_CON.txt");
+        assert_eq!(sanitize_filename("com1.txt"), "_com1.txt");
+        assert_eq!(sanitize_filename(" name. .png"), "name.png");
+    }
+
+    #[test]
+    fn sanitize_filename_truncates_long_names() {
+        let long = format!("{}.png", "a".repeat(300));
+        let sanitized = sanitize_filename(&long);
+        assert_eq!(sanitized.len(), MAX_FILENAME_BYTES);
+        assert!(sanitized.ends_with("a.png"));
+
+        // never split a multi-byte character
+        let long = format!("{}.png", "あ".repeat(300));
+        let sanitized = sanitize_filename(&long);
+        assert!(sanitized.len() <= MAX_FILENAME_BYTES);
+        assert!(sanitized.ends_with("あ.png"));
+    }
+
+    #[test]
+    fn with_counter_numbers_the_stem() {
+        assert_eq!(with_counter("image.png", 2), "image (2).png");
+        assert_eq!(with_counter("README", 3), "README (3)");
+    }
+
+    #[test]
+    fn colliding_names_are_numbered_without_original_name() {
+        let body = PostBody {
+            files: Some(vec![
+                attachment("a", "image", "png"),
+                attachment("b", "image", "png"),
+                attachment("c", "IMAGE", "png"),
+            ]),
+            ..Default::default()
+        };
+        let files = body.files(AuthorId::new(1), PostId::new(1));
+        let names: Vec<&str> = files.iter().map(|file| file.filename.as_str()).collect();
+        assert_eq!(names, ["image.png", "image (2).png", "IMAGE (3).png"]);
+        assert!(files
+            .iter()
+            .all(|file| file.extra.get("original_name").is_none()));
+    }
+
+    #[test]
+    fn sanitized_names_keep_original_name() {
+        let body = PostBody {
+            files: Some(vec![attachment("a", "a:b", "png")]),
+            ..Default::default()
+        };
+        let files = body.files(AuthorId::new(1), PostId::new(1));
+        assert_eq!(files[0].filename, "a_b.png");
+        assert_eq!(files[0].extra["original_name"], "a:b.png");
     }
 }
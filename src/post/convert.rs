@@ -0,0 +1,57 @@
+use std::path::{Path, PathBuf};
+
+use image::{DynamicImage, ImageReader};
+use sha2::{Digest, Sha256};
+use webp::Encoder;
+
+use crate::config::image_conversion::ImageConversion;
+
+/// A downloaded image re-encoded by `--convert-images`
+pub struct ConvertedImage {
+    pub path: PathBuf,
+    pub saved: u64,
+    pub sha256: String,
+}
+
+/// Re-encode a downloaded image as WebP next to it and remove the original
+///
+/// Returns `None` and keeps the original when the WebP would not be smaller.
+pub fn convert_image(
+    path: &Path,
+    conversion: ImageConversion,
+) -> Result<Option<ConvertedImage>, String> {
+    let original_size = std::fs::metadata(path).map_err(|e| e.to_string())?.len();
+    let image = ImageReader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| e.to_string())?
+        .decode()
+        .map_err(|e| e.to_string())?;
+    // the encoder only takes 8 bit RGB and RGBA
+    let image = if image.color().has_alpha() {
+        DynamicImage::ImageRgba8(image.to_rgba8())
+    } else {
+        DynamicImage::ImageRgb8(image.to_rgb8())
+    };
+
+    let encoder = Encoder::from_image(&image)?;
+    let webp = match conversion.quality() {
+        Some(quality) => encoder.encode(quality),
+        None => encoder.encode_lossless(),
+    };
+    if webp.len() as u64 >= original_size {
+        return Ok(None);
+    }
+
+    let converted = path.with_extension("webp");
+    std::fs::write(&converted, &*webp).map_err(|e| e.to_string())?;
+    std::fs::remove_file(path).map_err(|e| e.to_string())?;
+    let sha256 = Sha256::digest(&*webp)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    Ok(Some(ConvertedImage {
+        path: converted,
+        saved: original_size - webp.len() as u64,
+        sha256,
+    }))
+}
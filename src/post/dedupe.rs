@@ -0,0 +1,98 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    path::{Path, PathBuf},
+};
+
+use log::debug;
+use post_archiver::{AuthorId, FileMetaId, PostId};
+use rusqlite::Transaction;
+
+/// Index of the checksums stored by `store_checksums`, owned by fanbox-archive
+///
+/// `file_metas` belongs to the post-archiver schema, the `fanbox_` prefix keeps the index apart
+/// from its own. It only speeds up lookups, other tools can ignore it or drop it safely.
+const SHA256_INDEX_SQL: &str = "CREATE INDEX IF NOT EXISTS fanbox_file_metas_sha256_idx ON file_metas (json_extract(extra, '$.sha256'))";
+
+/// Hardlink downloaded files which are identical to an archived file
+///
+/// `files` are `(file meta, path relative to the output, sha256)`, returns the number of linked files and the bytes freed.
+pub fn link_duplicates(
+    tx: &Transaction,
+    output: &Path,
+    files: &[(FileMetaId, PathBuf, String)],
+) -> Result<(usize, u64), rusqlite::Error> {
+    let downloaded: HashSet<FileMetaId> = files.iter().map(|(id, _, _)| *id).collect();
+    // only the checksums just downloaded are looked up, through the index
+    tx.execute(SHA256_INDEX_SQL, [])?;
+    let mut select_files_stmt = tx.prepare_cached(
+        "SELECT id, author, post, filename, json_extract(extra, '$.sha256') FROM file_metas
+        WHERE json_extract(extra, '$.sha256') IN (SELECT value FROM json_each(?))
+        ORDER BY id",
+    )?;
+
+    let hashes: Vec<&str> = files.iter().map(|(_, _, sha256)| sha256.as_str()).collect();
+    let hashes = serde_json::to_string(&hashes).unwrap();
+    let mut archived: HashMap<String, PathBuf> = HashMap::new();
+    let rows = select_files_stmt.query_map([hashes], |row| {
+        Ok((
+            row.get::<_, FileMetaId>(0)?,
+            row.get::<_, AuthorId>(1)?,
+            row.get::<_, PostId>(2)?,
+            row.get::<_, String>(3)?,
+            row.get::<_, String>(4)?,
+        ))
+    })?;
+    for row in rows {
+        let (id, author, post, filename, sha256) = row?;
+        // downloaded again, the stored checksum is stale
+        if downloaded.contains(&id) {
+            continue;
+        }
+        archived.entry(sha256).or_insert_with(|| {
+            PathBuf::from(author.to_string())
+                .join(post.to_string())
+                .join(filename)
+        });
+    }
+
+    let (mut linked, mut freed) = (0, 0);
+    for (_, path, sha256) in files {
+        match archived.get(sha256) {
+            Some(original) if original != path => {
+                match link_file(&output.join(original), &output.join(path)) {
+                    Ok(size) => {
+                        debug!("Linked {} to {}", path.display(), original.display());
+                        linked += 1;
+                        freed += size;
+                    }
+                    // keep the copy, e.g. the filesystem has no hardlinks
+                    Err(e) => debug!("Failed to link {}: {}", path.display(), e),
+                }
+            }
+            Some(_) => {}
+            None => {
+                archived.insert(sha256.clone(), path.clone());
+            }
+        }
+    }
+    Ok((linked, freed))
+}
+
+/// Replace `path` with a hardlink to `original`, returning the bytes freed
+fn link_file(original: &Path, path: &Path) -> io::Result<u64> {
+    let size = std::fs::metadata(path)?.len();
+    if std::fs::metadata(original)?.len() != size {
+        return Err(io::Error::other("the archived file was changed"));
+    }
+
+    let mut link = path.as_os_str().to_owned();
+    link.push(".link");
+    let link = PathBuf::from(link);
+    std::fs::hard_link(original, &link)?;
+    if let Err(e) = std::fs::rename(&link, path) {
+        std::fs::remove_file(&link).ok();
+        return Err(e);
+    }
+    Ok(size)
+}
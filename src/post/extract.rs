@@ -0,0 +1,92 @@
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::{Component, Path, PathBuf},
+};
+
+use encoding_rs::SHIFT_JIS;
+use zip::ZipArchive;
+
+/// Unpack a zip into a folder named after it, returning the extracted entries
+/// example: `files.zip` -> `files/01.png`
+///
+/// Entries escaping the folder are skipped, the zip is rejected when its content exceeds `max_size`.
+/// The zip is unpacked next to the folder first, so a rejected zip leaves nothing behind.
+pub fn extract_archive(path: &Path, max_size: u64) -> Result<Vec<String>, String> {
+    let folder = path.with_extension("");
+    let unpacking = path.with_extension("unpacking");
+    if unpacking.exists() {
+        std::fs::remove_dir_all(&unpacking).map_err(|e| e.to_string())?;
+    }
+
+    let extracted = match unpack(path, &unpacking, max_size) {
+        Ok(extracted) => extracted,
+        Err(e) => {
+            std::fs::remove_dir_all(&unpacking).ok();
+            return Err(e);
+        }
+    };
+    if folder.exists() {
+        std::fs::remove_dir_all(&folder).map_err(|e| e.to_string())?;
+    }
+    std::fs::rename(&unpacking, &folder).map_err(|e| e.to_string())?;
+    Ok(extracted)
+}
+
+fn unpack(path: &Path, folder: &Path, max_size: u64) -> Result<Vec<String>, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    let mut archive = ZipArchive::new(file).map_err(|e| e.to_string())?;
+    std::fs::create_dir_all(folder).map_err(|e| e.to_string())?;
+
+    let mut extracted = vec![];
+    let mut remaining = max_size;
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index).map_err(|e| e.to_string())?;
+        if !entry.is_file() {
+            continue;
+        }
+        let name = decode_name(entry.name_raw());
+        let Some(relative) = sanitize_path(&name) else {
+            continue;
+        };
+        if entry.size() > remaining {
+            return Err(format!("the content is larger than {} bytes", max_size));
+        }
+
+        let target = folder.join(&relative);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        let mut output = File::create(&target).map_err(|e| e.to_string())?;
+        // the declared size may lie, never write more than the limit
+        let written = io::copy(&mut (&mut entry).take(remaining + 1), &mut output)
+            .map_err(|e| e.to_string())?;
+        if written > remaining {
+            return Err(format!("the content is larger than {} bytes", max_size));
+        }
+        remaining -= written;
+        extracted.push(relative.to_string_lossy().replace('\\', "/"));
+    }
+    Ok(extracted)
+}
+
+/// Decode an entry name, zips made on Japanese Windows use Shift-JIS without flagging it
+fn decode_name(raw: &[u8]) -> String {
+    match std::str::from_utf8(raw) {
+        Ok(name) => name.to_string(),
+        Err(_) => SHIFT_JIS.decode(raw).0.to_string(),
+    }
+}
+
+/// Keep only plain components, so an entry can not escape the folder
+fn sanitize_path(name: &str) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    for component in Path::new(&name.replace('\\', "/")).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    (!path.as_os_str().is_empty()).then_some(path)
+}
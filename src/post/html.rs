@@ -0,0 +1,321 @@
+/// A start tag of a HTML fragment, with its lowercase name and attributes
+#[derive(Debug, Clone, Default)]
+pub struct Tag {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+}
+
+impl Tag {
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// A HTML fragment scanned into its start tags and visible text
+///
+/// This is not a full HTML parser, only enough to read the snippets fanbox embeds.
+#[derive(Debug, Clone, Default)]
+pub struct Fragment {
+    pub tags: Vec<Tag>,
+    pub text: String,
+    /// Text of the `<title>`, if any
+    pub title: Option<String>,
+}
+
+impl Fragment {
+    pub fn parse(html: &str) -> Self {
+        let mut fragment = Self::default();
+        let mut text = String::new();
+        let mut rest = html;
+
+        while let Some(start) = rest.find('<') {
+            text += &rest[..start];
+            rest = &rest[start..];
+
+            if let Some(comment) = rest.strip_prefix("<!--") {
+                rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+                continue;
+            }
+
+            let (tag, closing, after) = parse_tag(&rest[1..]);
+            rest = after;
+            // block elements separate the words around them
+            if matches!(
+                tag.name.as_str(),
+                "p" | "div" | "br" | "blockquote" | "li" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6"
+            ) {
+                text.push(' ');
+            }
+            if closing || tag.name.is_empty() {
+                continue;
+            }
+
+            if tag.name == "title" && fragment.title.is_none() {
+                let end = find_ignore_case(rest, "</title").unwrap_or(rest.len());
+                let title = unescape(&rest[..end]);
+                fragment.title = Some(title.split_whitespace().collect::<Vec<_>>().join(" "));
+            }
+            // the content of these never shows up on the page
+            if matches!(tag.name.as_str(), "script" | "style") {
+                let close = format!("</{}", tag.name);
+                rest = find_ignore_case(rest, &close).map_or("", |end| &rest[end..]);
+            }
+            fragment.tags.push(tag);
+        }
+        text += rest;
+
+        fragment.text = unescape(&text)
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ");
+        fragment
+    }
+
+    /// Get an attribute of the first tag with this name
+    pub fn find(&self, tag: &str, attribute: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .filter(|t| t.name == tag)
+            .find_map(|t| t.attribute(attribute))
+            .filter(|value| !value.is_empty())
+    }
+
+    /// Get the content of a `<meta>` by its property or name
+    /// example: `og:description`
+    pub fn meta(&self, property: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .filter(|t| t.name == "meta")
+            .filter(|t| t.attribute("property").or(t.attribute("name")) == Some(property))
+            .find_map(|t| t.attribute("content"))
+            .filter(|value| !value.trim().is_empty())
+    }
+}
+
+/// Parse a tag after its `<`, returning it, whether it is a closing tag and the rest
+fn parse_tag(input: &str) -> (Tag, bool, &str) {
+    let (closing, mut rest) = match input.strip_prefix('/') {
+        Some(rest) => (true, rest),
+        None => (false, input),
+    };
+
+    let end = rest
+        .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .unwrap_or(rest.len());
+    let mut tag = Tag {
+        name: rest[..end].to_lowercase(),
+        attributes: vec![],
+    };
+    rest = &rest[end..];
+
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+        if rest.is_empty() {
+            return (tag, closing, rest);
+        }
+        if let Some(after) = rest.strip_prefix('>') {
+            return (tag, closing, after);
+        }
+
+        let end = rest
+            .find(|c: char| c.is_whitespace() || c == '=' || c == '>' || c == '/')
+            .unwrap_or(rest.len());
+        let name = rest[..end].to_lowercase();
+        rest = rest[end..].trim_start();
+
+        let mut value = String::new();
+        if let Some(after) = rest.strip_prefix('=') {
+            let after = after.trim_start();
+            let (raw, after) = match after.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    let inner = &after[1..];
+                    match inner.find(quote) {
+                        Some(end) => (&inner[..end], &inner[end + 1..]),
+                        None => (inner, ""),
+                    }
+                }
+                _ => {
+                    let end = after
+                        .find(|c: char| c.is_whitespace() || c == '>')
+                        .unwrap_or(after.len());
+                    (&after[..end], &after[end..])
+                }
+            };
+            value = unescape(raw);
+            rest = after;
+        }
+        if !name.is_empty() {
+            tag.attributes.push((name, value));
+        }
+    }
+}
+
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack
+        .char_indices()
+        .map(|(index, _)| index)
+        .find(|index| {
+            haystack
+                .get(*index..*index + needle.len())
+                .is_some_and(|part| part.eq_ignore_ascii_case(needle))
+        })
+}
+
+/// Encode a text so it can be put in HTML, also inside of attributes
+/// example: `<a & "b">` -> `&lt;a &amp; &quot;b&quot;&gt;`
+pub fn escape(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    for char in text.chars() {
+        match char {
+            '&' => output += "&amp;",
+            '<' => output += "&lt;",
+            '>' => output += "&gt;",
+            '"' => output += "&quot;",
+            '\'' => output += "&#39;",
+            char => output.push(char),
+        }
+    }
+    output
+}
+
+/// Decode the character references of a HTML text
+/// example: `a&amp;b&#x27;c` -> `a&b'c`
+pub fn unescape(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        output += &rest[..start];
+        rest = &rest[start..];
+
+        let decoded = rest.find(';').filter(|end| *end <= 10).and_then(|end| {
+            let reference = &rest[1..end];
+            let char = match reference {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                "ndash" => Some('–'),
+                "mdash" => Some('—'),
+                "hellip" => Some('…'),
+                _ => match reference.strip_prefix('#') {
+                    Some(hex) if hex.starts_with(['x', 'X']) => u32::from_str_radix(&hex[1..], 16)
+                        .ok()
+                        .and_then(char::from_u32),
+                    Some(decimal) => decimal.parse().ok().and_then(char::from_u32),
+                    None => None,
+                },
+            };
+            char.map(|char| (char, end))
+        });
+
+        match decoded {
+            Some((char, end)) => {
+                output.push(char);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                output.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    output + rest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_twitter_embed() {
+        let fragment = Fragment::parse(
+            r#"<blockquote class="twitter-tweet"><p lang="ja" dir="ltr">New work &amp; more<br>today</p>&mdash; Artist (@artist) <a href="https://twitter.com/artist/status/1?ref_src=twsrc%5Etfw">January 1, 2024</a></blockquote> <script async src="https://platform.twitter.com/widgets.js" charset="utf-8">var x = "<b>";</script>"#,
+        );
+        assert_eq!(
+            fragment.find("a", "href"),
+            Some("https://twitter.com/artist/status/1?ref_src=twsrc%5Etfw")
+        );
+        assert_eq!(
+            fragment.find("script", "src"),
+            Some("https://platform.twitter.com/widgets.js")
+        );
+        assert_eq!(fragment.find("iframe", "src"), None);
+        // script content is not text
+        assert_eq!(
+            fragment.text,
+            "New work & more today — Artist (@artist) January 1, 2024"
+        );
+    }
+
+    #[test]
+    fn parse_youtube_embed() {
+        let fragment = Fragment::parse(
+            r#"<iframe width="560" height="315" src="https://www.youtube.com/embed/abc?si=x&amp;t=1" title="YouTube video player" frameborder="0" allowfullscreen></iframe>"#,
+        );
+        assert_eq!(
+            fragment.find("iframe", "src"),
+            Some("https://www.youtube.com/embed/abc?si=x&t=1")
+        );
+        assert_eq!(fragment.find("iframe", "allowfullscreen"), None);
+        assert!(fragment.text.is_empty());
+    }
+
+    #[test]
+    fn parse_generic_card() {
+        let fragment = Fragment::parse(
+            "<html><head><title>\n  Shop &#8211; Page\n</title><meta property=\"og:title\" content=\"Shop\"><meta name=\"description\" content=\"Goods\"></head><body><!-- <a href=\"https://hidden.example\"> --><div>Visit</div><a href=https://shop.example/item>item</a></body></html>",
+        );
+        assert_eq!(fragment.title.as_deref(), Some("Shop – Page"));
+        assert_eq!(fragment.meta("og:title"), Some("Shop"));
+        assert_eq!(fragment.meta("description"), Some("Goods"));
+        assert_eq!(fragment.meta("og:image"), None);
+        assert_eq!(
+            fragment.find("a", "href"),
+            Some("https://shop.example/item")
+        );
+        assert_eq!(fragment.text, "Shop – Page Visit item");
+    }
+
+    #[test]
+    fn parse_single_quoted_and_reordered_attributes() {
+        let fragment = Fragment::parse(
+            "<META content='It&#39;s \"quoted\"' Property='og:description'/><IFRAME allow=\"autoplay\" SRC='https://player.example/1' width=1></IFRAME>",
+        );
+        assert_eq!(fragment.meta("og:description"), Some("It's \"quoted\""));
+        assert_eq!(
+            fragment.find("iframe", "src"),
+            Some("https://player.example/1")
+        );
+        assert_eq!(fragment.find("iframe", "width"), Some("1"));
+    }
+
+    #[test]
+    fn parse_without_iframe() {
+        let fragment = Fragment::parse("<p>Just text</p>");
+        assert_eq!(fragment.find("iframe", "src"), None);
+        assert_eq!(fragment.find("a", "href"), None);
+        assert_eq!(fragment.title, None);
+        assert_eq!(fragment.text, "Just text");
+    }
+
+    #[test]
+    fn unescape_references() {
+        assert_eq!(unescape("a&amp;b&#x27;c&#39;d&lt;&gt;"), "a&b'c'd<>");
+        assert_eq!(unescape("&hellip;&nbsp;&ndash;"), "… –");
+        // unknown or unterminated references are kept as they are
+        assert_eq!(unescape("AT&T &unknown; & &amp"), "AT&T &unknown; & &amp");
+        assert_eq!(unescape("&#xD800;"), "&#xD800;");
+    }
+
+    #[test]
+    fn escape_round_trips() {
+        let text = "<a & \"b\" 'c'>";
+        assert_eq!(escape(text), "&lt;a &amp; &quot;b&quot; &#39;c&#39;&gt;");
+        assert_eq!(unescape(&escape(text)), text);
+    }
+}
@@ -0,0 +1,85 @@
+use std::{collections::HashMap, path::Path};
+
+use chrono::{DateTime, Utc};
+use log::warn;
+use post_archiver::{AuthorId, Content, FileMetaId, PostId};
+use rusqlite::{params, Connection};
+
+/// Write `post.md` next to the files of each post, rendered from the archived content
+///
+/// Returns the number of files written, unchanged files are left alone.
+/// A file which can't be written is only warned about, the archive is already committed.
+pub fn write_markdown(
+    conn: &Connection,
+    output: &Path,
+    posts: &[PostId],
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut select_post_stmt = conn.prepare_cached(
+        "SELECT author, source, title, content, updated, published FROM posts WHERE id = ?",
+    )?;
+    let mut select_tags_stmt = conn.prepare_cached(
+        "SELECT name FROM tags JOIN post_tags ON tags.id = post_tags.tag WHERE post_tags.post = ? ORDER BY name",
+    )?;
+    let mut select_files_stmt =
+        conn.prepare_cached("SELECT id, filename, mime FROM file_metas WHERE post = ?")?;
+
+    let mut written = 0;
+    for post in posts {
+        let (author, source, title, content, updated, published) =
+            select_post_stmt.query_row(params![post], |row| {
+                Ok((
+                    row.get::<_, AuthorId>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, DateTime<Utc>>(4)?,
+                    row.get::<_, DateTime<Utc>>(5)?,
+                ))
+            })?;
+        let tags = select_tags_stmt
+            .query_map(params![post], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        let files: HashMap<FileMetaId, (String, String)> = select_files_stmt
+            .query_map(params![post], |row| {
+                Ok((row.get(0)?, (row.get(1)?, row.get(2)?)))
+            })?
+            .collect::<Result<_, _>>()?;
+        let content: Vec<Content> = serde_json::from_str(&content)?;
+
+        let mut markdown = format!("# {}\n\n", title);
+        markdown += &format!("- Published: {}\n", published.format("%Y-%m-%d %H:%M"));
+        markdown += &format!("- Updated: {}\n", updated.format("%Y-%m-%d %H:%M"));
+        if let Some(source) = source {
+            markdown += &format!("- Source: <{}>\n", source);
+        }
+        if !tags.is_empty() {
+            markdown += &format!("- Tags: {}\n", tags.join(", "));
+        }
+        markdown += "\n---\n\n";
+        let blocks: Vec<String> = content
+            .iter()
+            .map(|item| match item {
+                Content::Text(text) => text.clone(),
+                Content::File(id) => match files.get(id) {
+                    Some((filename, mime)) if mime.starts_with("image/") => {
+                        format!("![{}](<{}>)", filename, filename)
+                    }
+                    Some((filename, _)) => format!("[{}](<{}>)", filename, filename),
+                    None => format!("[missing file {}]", id),
+                },
+            })
+            .collect();
+        markdown += &blocks.join("\n\n");
+        markdown.push('\n');
+
+        let folder = output.join(author.to_string()).join(post.to_string());
+        let path = folder.join("post.md");
+        if std::fs::read_to_string(&path).ok().as_ref() != Some(&markdown) {
+            match std::fs::create_dir_all(&folder).and_then(|_| std::fs::write(&path, markdown)) {
+                Ok(()) => written += 1,
+                Err(e) => warn!("Failed to write {}: {}", path.display(), e),
+            }
+        }
+    }
+    Ok(written)
+}
@@ -1,140 +1,492 @@
 mod body;
+mod convert;
+mod dedupe;
+mod extract;
+mod html;
+mod markdown;
+mod preview;
+mod sidecar;
+mod tweet;
+mod validate;
 
-use std::path::PathBuf;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::FileTimes,
+    path::{Path, PathBuf},
+    time::{Instant, SystemTime},
+};
 
 use crate::{
-    api::fanbox::FanboxClient,
-    config::Config,
+    api::fanbox::{FanboxClient, FileTooLarge},
+    config::{content_format::ContentFormat, sidecar_type::SidecarType, Config},
     creator::SyncedCreator,
     fanbox::{Creator, Post, PostBody, PostListItem},
+    summary::{format_bytes, CreatorSummary},
 };
+use body::{PostFileMeta, COVER_ID};
 use chrono::{DateTime, Utc};
+use convert::convert_image;
+use extract::extract_archive;
 use futures::future::join_all;
-use log::{error, info};
+use log::{debug, error, info, warn};
+use markdown::write_markdown;
 use post_archiver::{AuthorId, Content, FileMetaId, PostId, PostTagId};
+use preview::fetch_link_previews;
 use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use sidecar::FileSidecar;
+use tweet::fetch_tweets;
+use validate::validate_image;
+
+/// Tag of posts with files which failed to download
+const INCOMPLETE_TAG: &str = "incomplete";
+/// Tag of restricted posts archived as stubs, see `--archive-restricted-stubs`
+const RESTRICTED_TAG: &str = "restricted";
+/// Tag of posts which content is HTML instead of Markdown, see `--content-format`
+const HTML_CONTENT_TAG: &str = "format:html";
 
 pub async fn get_post_urls(
     config: &Config,
     creator: &Creator,
 ) -> Result<Vec<PostListItem>, Box<dyn std::error::Error>> {
-    let client = FanboxClient::new(&config);
-    let mut items = client.get_posts(creator).await?;
+    let client = FanboxClient::new(config);
+    let items = client.get_posts(creator).await?;
+    Ok(dedup_posts(items))
+}
+
+/// Apply the post filters of the config and `--max-posts-per-creator`
+pub fn filter_posts(config: &Config, mut items: Vec<PostListItem>) -> Vec<PostListItem> {
     items.retain(|item| config.filter_post(item));
-    Ok(items)
+
+    if let Some(max_posts) = config.max_posts_per_creator() {
+        if items.len() > max_posts {
+            info!(
+                "Found {} posts, truncated to {} most recent",
+                items.len(),
+                max_posts
+            );
+            items.sort_by_key(|item| std::cmp::Reverse(item.published_datetime));
+            items.truncate(max_posts);
+        }
+    }
+
+    items
+}
+
+/// Remove duplicate posts by id, keeping the most recently updated copy
+fn dedup_posts(posts: Vec<PostListItem>) -> Vec<PostListItem> {
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut items: Vec<PostListItem> = Vec::with_capacity(posts.len());
+    for post in posts {
+        match seen.get(&post.id) {
+            Some(&index) => {
+                if items[index].updated_datetime < post.updated_datetime {
+                    items[index] = post;
+                }
+            }
+            None => {
+                seen.insert(post.id.clone(), items.len());
+                items.push(post);
+            }
+        }
+    }
+    items
+}
+
+/// Tag archived posts which are no longer listed by the creator with `deleted`
+///
+/// `posts` must be the complete post list of the creator, posts listed again lose the tag.
+pub fn mark_deleted_posts(
+    conn: &mut Connection,
+    creator_id: &str,
+    posts: &[PostListItem],
+) -> Result<usize, rusqlite::Error> {
+    let live: HashSet<String> = posts
+        .iter()
+        .map(|post| get_source_link(&post.creator_id, &post.id))
+        .collect();
+    let (start, end) = get_source_range(creator_id);
+
+    let tx = conn.transaction()?;
+    let deleted_tag = get_or_insert_tag(&tx, "deleted")?;
+    let mut deleted = 0;
+    {
+        let mut select_posts_stmt =
+            tx.prepare("SELECT id, source FROM posts WHERE source >= ? AND source < ?")?;
+        let mut insert_tag_stmt =
+            tx.prepare("INSERT OR IGNORE INTO post_tags (post,tag) VALUES (?,?)")?;
+        let mut delete_tag_stmt = tx.prepare("DELETE FROM post_tags WHERE post = ? AND tag = ?")?;
+
+        let archived = select_posts_stmt
+            .query_map(params![start, end], |row| {
+                Ok((row.get::<_, PostId>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        for (id, source) in archived {
+            if live.contains(&source) {
+                delete_tag_stmt.execute(params![id, deleted_tag])?;
+            } else {
+                deleted += insert_tag_stmt.execute(params![id, deleted_tag])?;
+            }
+        }
+    }
+    tx.commit()?;
+    Ok(deleted)
 }
 
+/// Keep posts which are new, updated since they were archived or missing files
+///
+/// The archived posts of the creator are loaded with one query instead of one per post.
 pub fn filter_unsynced_posts(
     conn: &mut Connection,
     mut posts: Vec<PostListItem>,
 ) -> Result<Vec<PostListItem>, rusqlite::Error> {
-    let mut stmt = conn.prepare("SELECT updated FROM posts WHERE source = ?")?;
-    posts.retain(|post| {
-        let source = get_source_link(&post.creator_id, &post.id);
-        let updated = post.updated_datetime;
+    let Some(first) = posts.first() else {
+        return Ok(posts);
+    };
 
-        let post_updated: Option<DateTime<Utc>> = stmt
-            .query_row(params![source], |row| row.get(0))
-            .optional()
-            .unwrap();
+    let started = Instant::now();
+    let (start, end) = get_source_range(&first.creator_id);
+    let mut stmt = conn.prepare(
+        "SELECT source, updated, EXISTS (
+            SELECT 1 FROM post_tags JOIN tags ON tags.id = post_tags.tag
+            WHERE post_tags.post = posts.id AND tags.name IN (?, ?)
+        ) FROM posts WHERE source >= ? AND source < ?",
+    )?;
+    let archived: HashMap<String, (DateTime<Utc>, bool)> = stmt
+        .query_map(params![INCOMPLETE_TAG, RESTRICTED_TAG, start, end], |row| {
+            Ok((row.get(0)?, (row.get(1)?, row.get(2)?)))
+        })?
+        .collect::<Result<_, _>>()?;
 
-        match post_updated {
-            Some(post_updated) => post_updated < updated,
-            None => false,
+    posts.retain(|post| {
+        let source = get_source_link(&post.creator_id, &post.id);
+        match archived.get(&source) {
+            // retry the files which failed to download last time,
+            // and check whether stubs can be archived in full now
+            Some((_, true)) => true,
+            Some((post_updated, false)) => *post_updated < post.updated_datetime,
+            // never archived, e.g. it was restricted before the pledge was raised
+            None => true,
         }
     });
+    debug!(
+        "Checked {} archived posts in {:.1}ms",
+        archived.len(),
+        started.elapsed().as_secs_f64() * 1000.0
+    );
     Ok(posts)
 }
 
+/// Get full posts, pushing the source links of the ones which failed to load
 pub async fn get_posts(
     config: &Config,
     posts: Vec<PostListItem>,
+    failed_posts: &mut Vec<String>,
 ) -> Result<Vec<Post>, Box<dyn std::error::Error>> {
-    let client = FanboxClient::new(&config);
+    let client = FanboxClient::new(config);
     let mut tasks = vec![];
     for post in posts {
         let client = client.clone();
-        tasks.push(tokio::spawn(async move {
-            client.get_post(post.id).await.expect("Failed to get post")
-        }));
+        let source = get_source_link(&post.creator_id, &post.id);
+        let post_id = post.id.clone();
+        let task = tokio::spawn(async move { client.get_post(post.id).await });
+        tasks.push((post_id, source, task));
     }
 
     let mut posts = Vec::new();
 
-    for task in tasks {
-        posts.push(task.await?);
+    for (post_id, source, task) in tasks {
+        let error = match task.await {
+            Ok(Ok(post)) => {
+                posts.push(post);
+                continue;
+            }
+            Ok(Err(e)) => e.to_string(),
+            Err(e) => format!("task stopped unexpectedly ({})", e),
+        };
+        if config.fail_fast() {
+            return Err(format!("Failed to get post {}: {}", post_id, error).into());
+        }
+        error!(post_id = post_id.as_str(); "Failed to get post {}: {}", post_id, error);
+        failed_posts.push(source);
     }
 
+    if config.fetch_link_previews() {
+        fetch_link_previews(&client, &mut posts).await;
+    }
+    if config.fetch_tweets() {
+        fetch_tweets(&client, &mut posts).await;
+    }
     Ok(posts)
 }
 
+/// Get posts given by `--post`, skipping the ones which failed to load
+pub async fn get_posts_by_id(config: &Config, ids: &[String]) -> Vec<Post> {
+    let client = FanboxClient::new(config);
+    let mut posts = vec![];
+    for id in ids {
+        match client.get_post(id.clone()).await {
+            Ok(post) => {
+                info!(" + {}: {}", id, post.title());
+                posts.push(post);
+            }
+            Err(e) => error!(post_id = id.as_str(); " + {}: failed ({})", id, e),
+        }
+    }
+    if config.fetch_link_previews() {
+        fetch_link_previews(&client, &mut posts).await;
+    }
+    if config.fetch_tweets() {
+        fetch_tweets(&client, &mut posts).await;
+    }
+    posts
+}
+
+/// An imported post and its updated time before, `None` when it is new
+type ImportedPost = (PostId, Option<DateTime<Utc>>);
+
 pub async fn sync_posts(
     conn: &mut Connection,
     config: &Config,
     creator: &SyncedCreator,
     posts: Vec<Post>,
     fanbox_and_free_tag: (PostTagId, PostTagId),
+    summary: &mut CreatorSummary,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let total_posts = posts.len();
     let mut synced_posts = 0;
 
     let mut all_files = vec![];
+    let mut synced_ids = vec![];
+    // source link and previous updated time of each synced post, to abort its import
+    let mut synced = HashMap::new();
+    // `(path, json)` of the revisions, only written once the new versions are committed
+    let mut revisions = vec![];
     let author = creator.author().id;
+    let started = Instant::now();
     let mut tx = conn.transaction()?;
     for post in posts {
-        info!(" syncing {}", post.title());
-        match sync_post(&mut tx, author, post, fanbox_and_free_tag) {
-            Ok(files) => {
+        info!(post_id = post.id.as_str(), creator_id = post.creator_id.as_str(); " syncing {}", post.title());
+        let post_id = post.id.clone();
+        let source = get_source_link(&post.creator_id, &post.id);
+        match sync_post(
+            &mut tx,
+            config,
+            author,
+            post,
+            fanbox_and_free_tag,
+            &mut revisions,
+        ) {
+            Ok(((id, previous), files, skipped)) => {
                 synced_posts += 1;
+                synced_ids.push(id);
+                synced.insert(id, (source, previous));
+                summary.skipped_files += skipped;
                 if !files.is_empty() {
                     all_files.extend(files);
                 }
             }
-            Err(e) => error!(" + failed: {}", e),
+            Err(e) if config.fail_fast() => return Err(e),
+            Err(e) => {
+                error!(post_id = post_id.as_str(); " + failed: {}", e);
+                summary.failed_posts.push(source);
+            }
         }
     }
 
+    summary.stages.sync += started.elapsed();
+    summary.posts += synced_posts;
     info!("{} total", total_posts);
     info!("{} success", synced_posts);
     if total_posts != synced_posts {
         info!("{} failed", total_posts - synced_posts);
     }
 
+    let mut incomplete = HashSet::new();
+    // folders of the removed posts, deleted once the removal is committed
+    let mut removed = vec![];
     if !all_files.is_empty() {
         info!("");
-        info!("Downloading {} files", all_files.len());
-        let client = FanboxClient::new(&config);
-        download_files(all_files, client, config.output()).await?;
+        let covers: HashSet<FileMetaId> = all_files
+            .iter()
+            .filter(|file| file.raw_id == COVER_ID)
+            .map(|file| file.id)
+            .collect();
+        let mut post_files: HashMap<PostId, usize> = HashMap::new();
+        for file in all_files.iter() {
+            *post_files.entry(file.post).or_default() += 1;
+        }
+        let client = FanboxClient::new(config);
+        let started = Instant::now();
+        let downloads = download_files(all_files, client, config).await?;
+        summary.stages.download += started.elapsed();
+        summary.files += downloads.files;
+        summary.bytes += downloads.bytes;
+        summary.failed_files += downloads.failed.len();
+        if !downloads.failed.is_empty() {
+            if config.fail_fast() {
+                let failed = downloads.failed.len();
+                return Err(format!("{} files failed to download", failed).into());
+            }
+            info!("{} files failed", downloads.failed.len());
+        }
+        incomplete.extend(downloads.failed.iter().map(|(post, ..)| *post));
+        let missing = downloads
+            .failed
+            .iter()
+            .map(|(post, file, filename, url)| (*post, *file, missing_placeholder(filename, url)));
+        replace_files(&tx, &missing.collect::<Vec<_>>())?;
+
+        // a post is not worth keeping without its cover or any of its files
+        let mut failed: HashMap<PostId, usize> = HashMap::new();
+        let mut aborted = HashSet::new();
+        for (post, file, ..) in downloads.failed.iter() {
+            *failed.entry(*post).or_default() += 1;
+            if covers.contains(file) {
+                aborted.insert(*post);
+            }
+        }
+        aborted.extend(
+            failed
+                .into_iter()
+                .filter(|(post, failed)| post_files.get(post) == Some(failed))
+                .map(|(post, _)| post),
+        );
+        for post in aborted {
+            let Some((source, previous)) = synced.remove(&post) else {
+                continue;
+            };
+            warn!("Import of {} aborted, its files failed to download", source);
+            abort_post(&tx, post, previous)?;
+            if previous.is_none() {
+                synced_ids.retain(|id| *id != post);
+                removed.push(
+                    config
+                        .output()
+                        .join(author.to_string())
+                        .join(post.to_string()),
+                );
+            }
+            summary.posts -= 1;
+            summary.failed_posts.push(source);
+        }
+        if !downloads.too_large.is_empty() {
+            info!("{} files skipped (too large)", downloads.too_large.len());
+            summary.skipped_files += downloads.too_large.len();
+            let too_large = downloads
+                .too_large
+                .iter()
+                .map(|(post, file, filename, size)| {
+                    (*post, *file, too_large_placeholder(filename, *size))
+                });
+            replace_files(&tx, &too_large.collect::<Vec<_>>())?;
+        }
+        summary.converted_files += downloads.converted.len();
+        summary.converted_saved += downloads.converted_saved;
+        store_conversions(&tx, &downloads.converted)?;
+        store_extractions(&tx, &downloads.extracted, config.keep_archives())?;
+
+        if config.dedupe_files() && !downloads.hashes.is_empty() {
+            let (linked, freed) = dedupe::link_duplicates(&tx, config.output(), &downloads.hashes)?;
+            if linked != 0 {
+                info!(
+                    "{} duplicate files linked ({} freed)",
+                    linked,
+                    format_bytes(freed)
+                );
+            }
+        }
+        store_checksums(&tx, &downloads.hashes)?;
+        store_dimensions(&tx, &downloads.dimensions)?;
     }
+    mark_incomplete_posts(&tx, &synced_ids, &incomplete)?;
     tx.commit()?;
+    for (path, revision) in revisions {
+        let written = std::fs::create_dir_all(path.parent().unwrap())
+            .and_then(|_| std::fs::write(&path, revision));
+        match written {
+            Ok(()) => info!("Revision saved ({})", path.display()),
+            Err(e) => warn!("Failed to save revision {}: {}", path.display(), e),
+        }
+    }
+    for folder in removed {
+        match std::fs::remove_dir_all(&folder) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                warn!("Failed to remove {}: {}", folder.display(), e)
+            }
+            _ => {}
+        }
+    }
+    if config.write_markdown() {
+        let written = write_markdown(conn, config.output(), &synced_ids)?;
+        debug!("{} post.md written", written);
+    }
 
     fn sync_post(
         tx: &mut Transaction,
+        config: &Config,
         author: AuthorId,
         post: Post,
         fanbox_and_free_tag: (PostTagId, PostTagId),
-    ) -> Result<Vec<SyncedFile>, Box<dyn std::error::Error>> {
-        let post_id = sync_post_meta(tx, author, &post, fanbox_and_free_tag)?;
+        revisions: &mut Vec<(PathBuf, String)>,
+    ) -> Result<(ImportedPost, Vec<SyncedFile>, usize), Box<dyn std::error::Error>> {
+        let revision = if config.keep_revisions() {
+            snapshot_revision(tx, config, &post)?
+        } else {
+            None
+        };
+        let (post_id, previous) = sync_post_meta(tx, config, author, &post, fanbox_and_free_tag)?;
         let body = post.body();
-        let files = sync_files(tx, &body, author, post_id)?;
-        let mapped = files
+        let (files, skipped) = sync_files(tx, config, &post, author, post_id)?;
+        let mut mapped: HashMap<String, Content> = files
             .iter()
-            .map(|file| (file.raw_id.clone(), file.id.into()))
+            .map(|file| (file.raw_id.clone(), Content::File(file.id)))
+            .collect();
+        for file in skipped.iter() {
+            mapped.insert(file.file.id.clone(), file.placeholder());
+        }
+        let mut content: Vec<Content> = body::excerpt(&post, config.content_format())
+            .into_iter()
             .collect();
-        sync_post_content(tx, post_id, body.content(&mapped))?;
+        content.extend(body.content(&mapped, config));
+        sync_post_content(tx, post_id, content)?;
+        mark_content_format(tx, post_id, config.content_format())?;
+        if let Some(cover) = files.iter().find(|file| file.raw_id == COVER_ID) {
+            let mut update_thumb_stmt =
+                tx.prepare_cached("UPDATE posts SET thumb = ? WHERE id = ?")?;
+            update_thumb_stmt.execute(params![cover.id, post_id])?;
+        }
         info!(" + {} files", files.len());
-        Ok(files)
+
+        let too_large = skipped
+            .iter()
+            .filter(|file| file.reason == SkipReason::TooLarge)
+            .count();
+        if too_large != 0 {
+            info!(" + {} files skipped (too large)", too_large);
+        }
+        let excluded = skipped
+            .iter()
+            .filter(|file| file.reason == SkipReason::Excluded)
+            .count();
+        if excluded != 0 {
+            info!(" + {} files excluded", excluded);
+        }
+        revisions.extend(revision);
+        Ok(((post_id, previous), files, too_large))
     }
 
     fn sync_post_meta(
         tx: &mut Transaction,
+        config: &Config,
         author: AuthorId,
         post: &Post,
         (fanbox_tag, free_tag): (PostTagId, PostTagId),
-    ) -> Result<PostId, Box<dyn std::error::Error>> {
-        let mut select_post_stmt = tx.prepare_cached("SELECT id FROM posts WHERE source = ?")?;
+    ) -> Result<ImportedPost, Box<dyn std::error::Error>> {
+        let mut select_post_stmt =
+            tx.prepare_cached("SELECT id, updated FROM posts WHERE source = ?")?;
         let mut update_post_stmt =
-            tx.prepare_cached("UPDATE posts SET updated = ? WHERE id = ?")?;
+            tx.prepare_cached("UPDATE posts SET updated = ?1 WHERE id = ?2 AND updated != ?1")?;
         let mut insert_post_stmt = tx.prepare_cached("INSERT INTO posts (author,source,title,content,updated,published) VALUES (?,?,?,?,?,?) RETURNING id")?;
         let mut insert_tag_stmt =
             tx.prepare_cached("INSERT OR IGNORE INTO post_tags (post,tag) VALUES (?,?)")?;
@@ -145,12 +497,12 @@ pub async fn sync_posts(
         let updated = post.updated_datetime;
         let published = post.published_datetime;
 
-        let post_id: PostId = match select_post_stmt
-            .query_row(params![source], |row| row.get(0))
+        let previous: Option<(PostId, DateTime<Utc>)> = select_post_stmt
+            .query_row(params![source], |row| Ok((row.get(0)?, row.get(1)?)))
             .optional()
-            .unwrap()
-        {
-            Some(id) => {
+            .unwrap();
+        let post_id: PostId = match previous {
+            Some((id, _)) => {
                 update_post_stmt.execute(params![updated, id]).unwrap();
                 id
             }
@@ -169,7 +521,121 @@ pub async fn sync_posts(
             insert_tag_stmt.execute(params![post_id, free_tag]).unwrap();
         }
 
-        Ok(post_id)
+        // the plan required to see the post, e.g. `fee:1000`, kept current on edits
+        let fee = Some(post.fee_required).filter(|fee| *fee != 0);
+        replace_tag(tx, post_id, "fee", fee.map(|fee| fee.to_string()))?;
+        // an edit may change the type, only the current one is kept
+        replace_tag(tx, post_id, "type", Some(post.ty.as_str().to_string()))?;
+        // counts for sorting, refreshed on every re-import
+        replace_tag(tx, post_id, "likes", Some(post.like_count.to_string()))?;
+        replace_tag(
+            tx,
+            post_id,
+            "comments",
+            Some(post.comment_count.to_string()),
+        )?;
+
+        // a stub loses the tag once the full post is archived over it
+        let restricted_tag = get_or_insert_tag(tx, RESTRICTED_TAG)?;
+        if post.is_restricted {
+            insert_tag_stmt.execute(params![post_id, restricted_tag])?;
+        } else {
+            let mut delete_tag_stmt =
+                tx.prepare_cached("DELETE FROM post_tags WHERE post = ? AND tag = ?")?;
+            delete_tag_stmt.execute(params![post_id, restricted_tag])?;
+        }
+
+        let warnings = content_warnings(config.content_warning_tags(), &post.tags);
+        if !warnings.is_empty() {
+            let cw_tag = get_or_insert_tag(tx, "cw")?;
+            insert_tag_stmt.execute(params![post_id, cw_tag])?;
+            for warning in warnings {
+                let tag = get_or_insert_tag(tx, &format!("cw:{}", warning))?;
+                insert_tag_stmt.execute(params![post_id, tag])?;
+            }
+        }
+
+        Ok((post_id, previous.map(|(_, updated)| updated)))
+    }
+
+    /// Tag a post with `<prefix>:<value>`, removing its other tags with this prefix
+    fn replace_tag(
+        tx: &Transaction,
+        post_id: PostId,
+        prefix: &str,
+        value: Option<String>,
+    ) -> Result<(), rusqlite::Error> {
+        let tag = match value {
+            Some(value) => Some(get_or_insert_tag(tx, &format!("{}:{}", prefix, value))?),
+            None => None,
+        };
+        let mut delete_tags_stmt = tx.prepare_cached(
+            "DELETE FROM post_tags WHERE post = ?1 AND tag IS NOT ?2 AND tag IN (SELECT id FROM tags WHERE name LIKE ?3 || ':%')",
+        )?;
+        delete_tags_stmt.execute(params![post_id, tag, prefix])?;
+        if let Some(tag) = tag {
+            tx.prepare_cached("INSERT OR IGNORE INTO post_tags (post,tag) VALUES (?,?)")?
+                .execute(params![post_id, tag])?;
+        }
+        Ok(())
+    }
+
+    /// Snapshot the archived version of an edited post before it is overwritten
+    ///
+    /// Returns the path and json of the revision, written once the new version is committed.
+    /// example: `<author>/<post>/revisions/2024-01-01T00-00-00Z.json`
+    fn snapshot_revision(
+        tx: &mut Transaction,
+        config: &Config,
+        post: &Post,
+    ) -> Result<Option<(PathBuf, String)>, Box<dyn std::error::Error>> {
+        let mut select_post_stmt = tx.prepare_cached(
+            "SELECT id, author, title, content, updated, published FROM posts WHERE source = ?",
+        )?;
+        let mut select_tags_stmt = tx.prepare_cached(
+            "SELECT name FROM tags JOIN post_tags ON tags.id = post_tags.tag WHERE post_tags.post = ?",
+        )?;
+
+        let source = get_source_link(&post.creator(), &post.id());
+        let Some((id, author, title, content, updated, published)) = select_post_stmt
+            .query_row(params![source], |row| {
+                Ok((
+                    row.get::<_, PostId>(0)?,
+                    row.get::<_, AuthorId>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, DateTime<Utc>>(4)?,
+                    row.get::<_, DateTime<Utc>>(5)?,
+                ))
+            })
+            .optional()?
+        else {
+            return Ok(None);
+        };
+        if updated >= post.updated_datetime {
+            return Ok(None);
+        }
+
+        let tags = select_tags_stmt
+            .query_map(params![id], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        let content: serde_json::Value = serde_json::from_str(&content)?;
+        let revision = serde_json::json!({
+            "source": source,
+            "title": title,
+            "content": content,
+            "tags": tags,
+            "updated": updated,
+            "published": published,
+        });
+
+        let folder = config
+            .output()
+            .join(author.to_string())
+            .join(id.to_string())
+            .join("revisions");
+        let path = folder.join(format!("{}.json", updated.format("%Y-%m-%dT%H-%M-%SZ")));
+        Ok(Some((path, serde_json::to_string_pretty(&revision)?)))
     }
 
     fn sync_post_content(
@@ -178,67 +644,247 @@ pub async fn sync_posts(
         content: Vec<Content>,
     ) -> Result<(), Box<dyn std::error::Error>> {
         let mut insert_post_stmt =
-            tx.prepare_cached("UPDATE posts SET content = ? WHERE id = ?")?;
+            tx.prepare_cached("UPDATE posts SET content = ?1 WHERE id = ?2 AND content != ?1")?;
         insert_post_stmt.execute(params![serde_json::to_string(&content)?, post_id])?;
         Ok(())
     }
 
+    /// Record the format of the content, so archives mixing both can be read
+    fn mark_content_format(
+        tx: &mut Transaction,
+        post_id: PostId,
+        format: ContentFormat,
+    ) -> Result<(), rusqlite::Error> {
+        let html_tag = get_or_insert_tag(tx, HTML_CONTENT_TAG)?;
+        let sql = match format {
+            ContentFormat::Html => "INSERT OR IGNORE INTO post_tags (post,tag) VALUES (?,?)",
+            ContentFormat::Markdown => "DELETE FROM post_tags WHERE post = ? AND tag = ?",
+        };
+        tx.prepare_cached(sql)?
+            .execute(params![post_id, html_tag])?;
+        Ok(())
+    }
+
     Ok(())
 }
 
+/// Sync file metas of a post, returning the synced files and the files skipped by config
 fn sync_files(
     tx: &mut Transaction,
-    post_body: &PostBody,
+    config: &Config,
+    source: &Post,
     author: AuthorId,
     post: PostId,
-) -> Result<Vec<SyncedFile>, Box<dyn std::error::Error>> {
+) -> Result<(Vec<SyncedFile>, Vec<SkippedFile>), Box<dyn std::error::Error>> {
+    let mut select_file_stmt = tx.prepare_cached(
+        "SELECT id, filename FROM file_metas WHERE post = ?1 AND filename IN (?2, ?3, ?4) ORDER BY id",
+    )?;
+    // merge the extra, so the checksum and dimensions read from the file are kept
+    // attachments used to be archived with a `null` extra
+    let mut update_file_stmt = tx.prepare_cached(
+        "UPDATE file_metas SET mime = ?1, extra = json_patch(?2, iif(extra = 'null', '{}', extra)) WHERE id = ?3 AND (mime != ?1 OR extra != json_patch(?2, iif(extra = 'null', '{}', extra)))",
+    )?;
     let mut insert_file_stmt = tx.prepare_cached(
         "INSERT INTO file_metas (filename,author,post,mime,extra) VALUES (?,?,?,?,?) RETURNING id",
     )?;
-    let files = post_body.files(AuthorId::from(author), PostId::from(post));
+    let post_body: PostBody = source.body();
+    let mut files = post_body.files(author, post);
+    // the cover is all what a stub has to show
+    if source.is_restricted {
+        files.extend(PostFileMeta::from_cover(source, author, post));
+    }
+    files.extend(PostFileMeta::from_share_image(source, author, post));
     let mut collect = vec![];
+    let mut skipped = vec![];
+    // the same image may be listed in both `images` and `imageMap`
+    let mut seen: HashMap<String, FileMetaId> = HashMap::new();
+    let mut claimed: HashSet<FileMetaId> = HashSet::new();
     for file in files {
-        let id: FileMetaId = insert_file_stmt
-            .query_row(
+        if let Some(&id) = seen.get(&file.url) {
+            skipped.push(SkippedFile {
+                file,
+                reason: SkipReason::Duplicate(id),
+            });
+            continue;
+        }
+        if config.exclude_extension(&file.filename) {
+            skipped.push(SkippedFile {
+                file,
+                reason: SkipReason::Excluded,
+            });
+            continue;
+        }
+        if let (Some(size), Some(max_file_size)) = (file.size, config.max_file_size()) {
+            if size > max_file_size {
+                skipped.push(SkippedFile {
+                    file,
+                    reason: SkipReason::TooLarge,
+                });
+                continue;
+            }
+        }
+
+        let extra = serde_json::to_string(&file.extra).unwrap();
+        // an image converted by `--convert-images` is archived under its new name
+        let converted = config
+            .convert_images()
+            .and_then(|conversion| conversion.converted_filename(&file.filename));
+        let converted_filename = converted.as_ref().unwrap_or(&file.filename);
+        // archived before filenames were sanitized
+        let original_filename = file.extra["original_name"]
+            .as_str()
+            .unwrap_or(&file.filename);
+        // reuse the existing file meta when the post is re-imported,
+        // but never one already claimed by another file of the post
+        let existing = select_file_stmt
+            .query_map(
                 params![
-                    &file.filename,
-                    file.author,
                     file.post,
-                    &file.mime,
-                    serde_json::to_string(&file.extra).unwrap(),
+                    &file.filename,
+                    converted_filename,
+                    original_filename
                 ],
-                |row| row.get(0),
-            )
-            .unwrap();
+                |row| Ok((row.get::<_, FileMetaId>(0)?, row.get::<_, String>(1)?)),
+            )?
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .find(|(id, _)| !claimed.contains(id));
+        let (id, filename): (FileMetaId, String) = match existing {
+            Some((id, filename)) => {
+                let mime = if converted.as_ref() == Some(&filename) {
+                    "image/webp"
+                } else {
+                    file.mime.as_str()
+                };
+                update_file_stmt.execute(params![mime, extra, id])?;
+                (id, filename)
+            }
+            None => {
+                let id = insert_file_stmt
+                    .query_row(
+                        params![&file.filename, file.author, file.post, &file.mime, extra],
+                        |row| row.get(0),
+                    )
+                    .unwrap();
+                (id, file.filename.clone())
+            }
+        };
+        let convert = converted.is_some() && converted.as_ref() != Some(&filename);
 
         let path = PathBuf::from(file.author.to_string())
             .join(file.post.to_string())
-            .join(&file.filename);
+            .join(&filename);
         let url = file.url.clone();
+        seen.insert(url.clone(), id);
+        claimed.insert(id);
+        let sidecar = FileSidecar::new(source, url.clone());
+        let mtime = match (config.touch_mtime(), config.mtime_from_updated()) {
+            (false, _) => None,
+            (true, false) => Some(source.published_datetime.into()),
+            (true, true) => Some(source.updated_datetime.into()),
+        };
         collect.push(SyncedFile {
             id,
+            post,
             path,
             url,
             raw_id: file.id,
+            // a converted image is smaller than the original fanbox reports
+            size: file.size.filter(|_| converted.as_ref() != Some(&filename)),
+            sidecar,
+            mtime,
+            convert,
         });
     }
-    Ok(collect)
+    Ok((collect, skipped))
+}
+
+/// Files downloaded by `download_files`
+#[derive(Debug, Default)]
+struct Downloads {
+    files: usize,
+    bytes: u64,
+    /// `(post, file meta, filename, url)` of each file which failed to download
+    failed: Vec<(PostId, FileMetaId, String, String)>,
+    /// `(post, file meta, filename, size)` of each file refused by `--max-file-size`
+    too_large: Vec<(PostId, FileMetaId, String, u64)>,
+    /// `(file meta, path, sha256)` of each file fetched from fanbox
+    hashes: Vec<(FileMetaId, PathBuf, String)>,
+    /// `(file meta, width, height)` of each image checked by `--validate-images`
+    dimensions: Vec<(FileMetaId, u32, u32)>,
+    /// `(file meta, new filename, original url)` of each image converted by `--convert-images`
+    converted: Vec<(FileMetaId, String, String)>,
+    /// Bytes saved by `--convert-images`
+    converted_saved: u64,
+    /// `(post, file meta, zip filename, entries)` of each zip unpacked by `--extract-archives`
+    extracted: Vec<(PostId, FileMetaId, String, Vec<String>)>,
+}
+
+/// Why a download task has no file
+enum DownloadFailure {
+    Failed,
+    /// Size reported by the server once the download started
+    TooLarge(u64),
+}
+
+/// Outcome of a single download task
+struct DownloadedFile {
+    bytes: u64,
+    sha256: Option<String>,
+    dimensions: Option<(u32, u32)>,
+    /// New filename and saved bytes of an image converted by `--convert-images`
+    converted: Option<(String, u64)>,
+    /// Entries unpacked by `--extract-archives`
+    extracted: Option<Vec<String>>,
 }
 
 async fn download_files(
     files: Vec<SyncedFile>,
     client: FanboxClient,
-    output: &PathBuf,
-) -> Result<(), Box<dyn std::error::Error>> {
+    config: &Config,
+) -> Result<Downloads, Box<dyn std::error::Error>> {
     let mut tasks = vec![];
+    let mut owners = vec![];
+    let validate_images = config.validate_images();
+    let convert_images = config.convert_images();
+    let extract_archives = config.extract_archives();
+    let keep_archives = config.keep_archives();
+    let max_extract_size = config.max_extract_size();
+    let sidecar = config.sidecar();
+    let mut downloads = Downloads::default();
+    // `(post, queued, reused)`, the files of a post come one after another
+    let mut counts: Vec<(PostId, usize, usize)> = vec![];
 
     let mut last_folder = PathBuf::new();
     for file in files {
-        let path = output.join(&file.path);
+        let path = config.output().join(&file.path);
+        if counts.last().is_none_or(|(post, ..)| *post != file.post) {
+            counts.push((file.post, 0, 0));
+        }
+        let (_, queued, reused) = counts.last_mut().unwrap();
 
         if !client.overwrite() && path.exists() {
-            info!("Download was skip ({})", path.display());
-            continue;
+            let corrupt = config.verify()
+                && file.size.is_some_and(|size| {
+                    std::fs::metadata(&path).is_ok_and(|metadata| metadata.len() != size)
+                });
+            if !corrupt {
+                debug!("Download was skip ({})", path.display());
+                *reused += 1;
+                if let Some(ty) = sidecar {
+                    write_sidecar(&path, &file.sidecar, ty).await;
+                }
+                continue;
+            }
+            info!("Size mismatch, downloading again ({})", path.display());
+            if let Err(e) = tokio::fs::remove_file(&path).await {
+                warn!("Failed to remove {}: {}", path.display(), e);
+                let filename = path.file_name().unwrap().to_string_lossy().to_string();
+                downloads
+                    .failed
+                    .push((file.post, file.id, filename, file.url));
+                continue;
+            }
         }
 
         // Create folder if it doesn't exist
@@ -248,20 +894,311 @@ async fn download_files(
             tokio::fs::create_dir_all(folder).await?;
         }
 
+        *queued += 1;
         let client = client.clone();
+        owners.push((file.post, file.id, file.path, file.url.clone()));
         tasks.push(tokio::spawn(async move {
-            client
-                .download(&file.url, path)
+            let (bytes, mut sha256) = match client.download(&file.url, path.clone()).await {
+                Ok(downloaded) => downloaded,
+                Err(e) => {
+                    if let Some(FileTooLarge { size }) = e.downcast_ref::<FileTooLarge>() {
+                        info!("Skipped {}: {}", file.url, e);
+                        return Err(DownloadFailure::TooLarge(*size));
+                    }
+                    error!("Failed to download {}: {}", file.url, e);
+                    return Err(DownloadFailure::Failed);
+                }
+            };
+
+            let mut dimensions = None;
+            if validate_images {
+                let checked = path.clone();
+                let validated = tokio::task::spawn_blocking(move || validate_image(&checked))
+                    .await
+                    .unwrap_or_else(|e| Err(e.to_string()));
+                match validated {
+                    Ok(probed) => dimensions = probed,
+                    Err(e) => {
+                        // remove it, so the next run downloads it again
+                        error!("Invalid image {}: {}", path.display(), e);
+                        tokio::fs::remove_file(&path).await.ok();
+                        return Err(DownloadFailure::Failed);
+                    }
+                }
+            }
+
+            let mut path = path;
+            let mut converted = None;
+            if let Some(conversion) = convert_images.filter(|_| file.convert) {
+                let original = path.clone();
+                let result =
+                    tokio::task::spawn_blocking(move || convert_image(&original, conversion))
+                        .await
+                        .unwrap_or_else(|e| Err(e.to_string()));
+                match result {
+                    Ok(Some(image)) => {
+                        path = image.path;
+                        sha256 = Some(image.sha256);
+                        converted = Some(image.saved);
+                    }
+                    Ok(None) => debug!("Kept {}, it would not be smaller", path.display()),
+                    // the original is still a valid download
+                    Err(e) => warn!("Failed to convert {}: {}", path.display(), e),
+                }
+            }
+
+            let mut extracted = None;
+            let is_zip = path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"));
+            if extract_archives && is_zip {
+                let archive = path.clone();
+                let result = tokio::task::spawn_blocking(move || {
+                    extract_archive(&archive, max_extract_size)
+                })
                 .await
-                .expect("Failed to download file");
+                .unwrap_or_else(|e| Err(e.to_string()));
+                match result {
+                    Ok(entries) => {
+                        info!("Extracted {} files from {}", entries.len(), path.display());
+                        if !keep_archives {
+                            tokio::fs::remove_file(&path).await.ok();
+                        }
+                        extracted = Some(entries);
+                    }
+                    // the zip itself is still archived
+                    Err(e) => warn!("Failed to extract {}: {}", path.display(), e),
+                }
+            }
+
+            // only files which made it get a sidecar
+            if let Some(ty) = sidecar.filter(|_| path.exists()) {
+                write_sidecar(&path, &file.sidecar, ty).await;
+            }
+            if let Some(mtime) = file.mtime.filter(|_| path.exists()) {
+                // some filesystems reject it, the download is still fine
+                if let Err(e) = set_file_times(&path, mtime) {
+                    debug!("Failed to set mtime of {}: {}", path.display(), e);
+                }
+            }
+            let filename = path.file_name().unwrap().to_string_lossy().to_string();
+            Ok(DownloadedFile {
+                bytes,
+                sha256,
+                dimensions,
+                converted: converted.map(|saved| (filename, saved)),
+                extracted,
+            })
         }));
     }
 
-    join_all(tasks).await;
+    for (post, queued, reused) in counts {
+        info!(
+            " + post {}: {} to download, {} reused",
+            post, queued, reused
+        );
+    }
+    info!("Downloading {} files", tasks.len());
+    let results = join_all(tasks).await;
+    for ((post, id, path, url), result) in owners.into_iter().zip(results) {
+        match result {
+            Ok(Ok(file)) => {
+                downloads.files += 1;
+                downloads.bytes += file.bytes;
+                let path = match file.converted {
+                    Some((filename, saved)) => {
+                        downloads.converted_saved += saved;
+                        let path = path.with_file_name(&filename);
+                        downloads.converted.push((id, filename, url));
+                        path
+                    }
+                    None => path,
+                };
+                let removed = file.extracted.is_some() && !keep_archives;
+                if let Some(entries) = file.extracted {
+                    let filename = path.file_name().unwrap().to_string_lossy().to_string();
+                    downloads.extracted.push((post, id, filename, entries));
+                }
+                if let Some(sha256) = file.sha256.filter(|_| !removed) {
+                    downloads.hashes.push((id, path, sha256));
+                }
+                if let Some((width, height)) = file.dimensions {
+                    downloads.dimensions.push((id, width, height));
+                }
+            }
+            Ok(Err(DownloadFailure::TooLarge(size))) => {
+                let filename = path.file_name().unwrap().to_string_lossy().to_string();
+                downloads.too_large.push((post, id, filename, size));
+            }
+            Ok(Err(DownloadFailure::Failed)) => {
+                let filename = path.file_name().unwrap().to_string_lossy().to_string();
+                downloads.failed.push((post, id, filename, url));
+            }
+            Err(e) => {
+                error!("Download task stopped unexpectedly ({})", e);
+                let filename = path.file_name().unwrap().to_string_lossy().to_string();
+                downloads.failed.push((post, id, filename, url));
+            }
+        }
+    }
+    Ok(downloads)
+}
+
+/// Set both access and modification time of a file
+fn set_file_times(path: &Path, time: SystemTime) -> std::io::Result<()> {
+    let times = FileTimes::new().set_accessed(time).set_modified(time);
+    std::fs::File::options()
+        .write(true)
+        .open(path)?
+        .set_times(times)
+}
+
+/// Store the sha256 of downloaded files in their extra, checked by `verify`
+fn store_checksums(
+    tx: &Transaction,
+    files: &[(FileMetaId, PathBuf, String)],
+) -> Result<(), rusqlite::Error> {
+    let mut update_file_stmt = tx.prepare_cached(
+        "UPDATE file_metas SET extra = json_set(extra, '$.sha256', ?) WHERE id = ?",
+    )?;
+    for (id, _, sha256) in files {
+        update_file_stmt.execute(params![sha256, id])?;
+    }
+    Ok(())
+}
+
+/// Rename the file metas of converted images, keeping the original url in their extra
+fn store_conversions(
+    tx: &Transaction,
+    files: &[(FileMetaId, String, String)],
+) -> Result<(), rusqlite::Error> {
+    let mut update_file_stmt = tx.prepare_cached(
+        "UPDATE file_metas SET filename = ?, mime = 'image/webp', extra = json_set(extra, '$.original_url', ?) WHERE id = ?",
+    )?;
+    for (id, filename, url) in files {
+        update_file_stmt.execute(params![filename, url, id])?;
+    }
+    Ok(())
+}
+
+/// List the entries of unpacked zips in the post content
+///
+/// Without `--keep-archives` the list replaces the removed zip and its file meta is deleted.
+fn store_extractions(
+    tx: &Transaction,
+    archives: &[(PostId, FileMetaId, String, Vec<String>)],
+    keep_archives: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut select_content_stmt = tx.prepare_cached("SELECT content FROM posts WHERE id = ?")?;
+    let mut update_content_stmt = tx.prepare_cached("UPDATE posts SET content = ? WHERE id = ?")?;
+    let mut delete_file_stmt = tx.prepare_cached("DELETE FROM file_metas WHERE id = ?")?;
+    for (post, file, filename, entries) in archives {
+        let content: String = select_content_stmt.query_row(params![post], |row| row.get(0))?;
+        let mut content: Vec<Content> = serde_json::from_str(&content)?;
+
+        let folder = Path::new(filename).with_extension("");
+        let list: Vec<String> = entries
+            .iter()
+            .map(|entry| format!("- `{}/{}`", folder.display(), entry))
+            .collect();
+        let text = Content::Text(format!("{}:\n\n{}\n", filename, list.join("\n")));
+        let position = content
+            .iter()
+            .position(|item| matches!(item, Content::File(id) if id == file));
+        match (position, keep_archives) {
+            (Some(position), true) => content.insert(position + 1, text),
+            (Some(position), false) => content[position] = text,
+            (None, _) => content.push(text),
+        }
+        update_content_stmt.execute(params![serde_json::to_string(&content)?, post])?;
+
+        if !keep_archives {
+            delete_file_stmt.execute(params![file])?;
+        }
+    }
+    Ok(())
+}
+
+/// Replace files which were never written with their placeholder in the post content
+///
+/// e.g. refused by `--max-file-size` or failed to download, their file metas are deleted.
+fn replace_files(
+    tx: &Transaction,
+    files: &[(PostId, FileMetaId, Content)],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut select_content_stmt = tx.prepare_cached("SELECT content FROM posts WHERE id = ?")?;
+    let mut update_content_stmt = tx.prepare_cached("UPDATE posts SET content = ? WHERE id = ?")?;
+    let mut clear_thumb_stmt =
+        tx.prepare_cached("UPDATE posts SET thumb = NULL WHERE id = ? AND thumb = ?")?;
+    let mut clear_author_thumb_stmt =
+        tx.prepare_cached("UPDATE authors SET thumb = NULL WHERE thumb = ?")?;
+    let mut delete_file_stmt = tx.prepare_cached("DELETE FROM file_metas WHERE id = ?")?;
+    for (post, file, placeholder) in files {
+        let content: String = select_content_stmt.query_row(params![post], |row| row.get(0))?;
+        let mut content: Vec<Content> = serde_json::from_str(&content)?;
+        for item in content.iter_mut() {
+            if matches!(item, Content::File(id) if id == file) {
+                *item = placeholder.clone();
+            }
+        }
+        update_content_stmt.execute(params![serde_json::to_string(&content)?, post])?;
+        clear_thumb_stmt.execute(params![post, file])?;
+        clear_author_thumb_stmt.execute(params![file])?;
+        delete_file_stmt.execute(params![file])?;
+    }
+    Ok(())
+}
+
+/// Replace the dimensions reported by fanbox with the ones read from the images
+fn store_dimensions(
+    tx: &Transaction,
+    files: &[(FileMetaId, u32, u32)],
+) -> Result<(), rusqlite::Error> {
+    let mut update_file_stmt = tx.prepare_cached(
+        "UPDATE file_metas SET extra = json_set(extra, '$.width', ?, '$.height', ?) WHERE id = ?",
+    )?;
+    for (id, width, height) in files {
+        update_file_stmt.execute(params![width, height, id])?;
+    }
+    Ok(())
+}
+
+/// Tag posts with missing files `incomplete`, so they are synced again on the next run
+///
+/// `synced` posts which are not `incomplete` lose the tag.
+fn mark_incomplete_posts(
+    tx: &Transaction,
+    synced: &[PostId],
+    incomplete: &HashSet<PostId>,
+) -> Result<(), rusqlite::Error> {
+    let incomplete_tag = get_or_insert_tag(tx, INCOMPLETE_TAG)?;
+    let mut insert_tag_stmt =
+        tx.prepare_cached("INSERT OR IGNORE INTO post_tags (post,tag) VALUES (?,?)")?;
+    let mut delete_tag_stmt =
+        tx.prepare_cached("DELETE FROM post_tags WHERE post = ? AND tag = ?")?;
+    for post in synced {
+        if incomplete.contains(post) {
+            insert_tag_stmt.execute(params![post, incomplete_tag])?;
+        } else {
+            delete_tag_stmt.execute(params![post, incomplete_tag])?;
+        }
+    }
     Ok(())
 }
 
-pub fn get_or_insert_tag(conn: &mut Connection, name: &str) -> Result<PostTagId, rusqlite::Error> {
+/// Write the sidecar of an archived file, a failure only loses the sidecar
+async fn write_sidecar(file: &Path, sidecar: &FileSidecar, ty: SidecarType) {
+    let path = FileSidecar::path(file, ty);
+    let content = sidecar.render(ty);
+    if tokio::fs::read_to_string(&path).await.ok().as_ref() == Some(&content) {
+        return;
+    }
+    if let Err(e) = tokio::fs::write(&path, content).await {
+        warn!("Failed to write sidecar {}: {}", path.display(), e);
+    }
+}
+
+pub fn get_or_insert_tag(conn: &Connection, name: &str) -> Result<PostTagId, rusqlite::Error> {
     match conn
         .query_row("SELECT id FROM tags WHERE name = ?", [name], |row| {
             row.get(0)
@@ -277,14 +1214,591 @@ pub fn get_or_insert_tag(conn: &mut Connection, name: &str) -> Result<PostTagId,
     }
 }
 
+/// Normalize a tag for matching, folding case and full-width characters
+/// example: `ＧＯＲＥ` -> `gore`
+fn normalize_tag(tag: &str) -> String {
+    tag.trim()
+        .chars()
+        .map(|char| match char {
+            '\u{FF01}'..='\u{FF5E}' => char::from_u32(char as u32 - 0xFEE0).unwrap(),
+            '\u{3000}' => ' ',
+            _ => char,
+        })
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Get the normalized content warnings of a post
+fn content_warnings(warning_tags: &[String], tags: &[String]) -> Vec<String> {
+    let tags: Vec<String> = tags.iter().map(|tag| normalize_tag(tag)).collect();
+    let mut warnings: Vec<String> = warning_tags
+        .iter()
+        .map(|tag| normalize_tag(tag))
+        .filter(|tag| tags.contains(tag))
+        .collect();
+    warnings.sort();
+    warnings.dedup();
+    warnings
+}
+
 pub fn get_source_link(creator_id: &str, post_id: &str) -> String {
     format!("https://{}.fanbox.cc/posts/{}", creator_id, post_id)
 }
 
+/// Range of the source links of a creator, so the `source` index can be used
+/// example: `https://xxx.fanbox.cc/posts/` to `https://xxx.fanbox.cc/posts0`
+pub fn get_source_range(creator_id: &str) -> (String, String) {
+    let start = get_source_link(creator_id, "");
+    let end = format!("{}0", start.trim_end_matches('/'));
+    (start, end)
+}
+
 #[derive(Debug)]
 pub struct SyncedFile {
+    pub post: PostId,
     pub path: PathBuf,
     pub url: String,
     pub raw_id: String,
     pub id: FileMetaId,
+    /// Size reported by fanbox, `None` when unknown or the file was converted
+    pub size: Option<u64>,
+    pub sidecar: FileSidecar,
+    /// Modification time to set once downloaded
+    pub mtime: Option<SystemTime>,
+    /// Convert the image once downloaded, see `--convert-images`
+    pub convert: bool,
+}
+
+#[derive(Debug)]
+pub struct SkippedFile {
+    pub file: PostFileMeta,
+    pub reason: SkipReason,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    TooLarge,
+    Excluded,
+    /// Same url as an earlier file of the post
+    Duplicate(FileMetaId),
+}
+
+/// Get the content standing in for a file over `--max-file-size`
+/// Undo the import of a post which lost its cover or all of its files
+///
+/// A new post is removed, so the next run imports it again.
+/// An archived post gets its previous updated time back and stays tagged incomplete.
+fn abort_post(
+    tx: &Transaction,
+    post: PostId,
+    previous: Option<DateTime<Utc>>,
+) -> Result<(), rusqlite::Error> {
+    if let Some(updated) = previous {
+        tx.prepare_cached("UPDATE posts SET updated = ? WHERE id = ?")?
+            .execute(params![updated, post])?;
+        return Ok(());
+    }
+    tx.prepare_cached(
+        "UPDATE authors SET thumb = NULL WHERE thumb IN (SELECT id FROM file_metas WHERE post = ?)",
+    )?
+    .execute(params![post])?;
+    tx.prepare_cached("DELETE FROM file_metas WHERE post = ?")?
+        .execute(params![post])?;
+    tx.prepare_cached("DELETE FROM post_tags WHERE post = ?")?
+        .execute(params![post])?;
+    tx.prepare_cached("DELETE FROM posts WHERE id = ?")?
+        .execute(params![post])?;
+    Ok(())
+}
+
+/// Placeholder of a file which failed to download, linking the original
+fn missing_placeholder(filename: &str, url: &str) -> Content {
+    Content::Text(format!("[missing: {}]({})", filename, url))
+}
+
+fn too_large_placeholder(filename: &str, size: u64) -> Content {
+    Content::Text(format!("[skipped: {}, {}]", filename, format_bytes(size)))
+}
+
+impl SkippedFile {
+    /// Get the content standing in for the skipped file
+    pub fn placeholder(&self) -> Content {
+        let file = &self.file;
+        match self.reason {
+            SkipReason::TooLarge => {
+                too_large_placeholder(&file.filename, file.size.unwrap_or_default())
+            }
+            SkipReason::Excluded => Content::Text(format!("[{}]({})", file.filename, file.url)),
+            SkipReason::Duplicate(id) => Content::File(id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fanbox::{PostFile, PostType, User};
+    use chrono::TimeZone;
+    use clap::Parser;
+
+    fn open_archive() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(post_archiver::utils::TEMPLATE_DATABASE_UP_SQL)
+            .unwrap();
+        conn.execute("INSERT INTO authors (name) VALUES ('creator')", [])
+            .unwrap();
+        conn
+    }
+
+    fn post(id: &str, body: PostBody) -> Post {
+        let datetime = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        Post {
+            id: id.to_string(),
+            title: format!("Post {}", id),
+            fee_required: 0,
+            published_datetime: datetime,
+            updated_datetime: datetime,
+            tags: vec![],
+            is_liked: false,
+            like_count: 0,
+            comment_count: 0,
+            is_restricted: false,
+            user: User {
+                icon_url: None,
+                name: "creator".to_string(),
+                user_id: "1".to_string(),
+            },
+            creator_id: "creator".to_string(),
+            has_adult_content: false,
+            ty: PostType::File,
+            cover_image_url: None,
+            body,
+            excerpt: String::new(),
+            next_post: None,
+            prev_post: None,
+            image_for_share: String::new(),
+            is_pinned: false,
+        }
+    }
+
+    fn attachment(id: &str, name: &str) -> PostFile {
+        PostFile {
+            id: id.to_string(),
+            name: name.to_string(),
+            extension: "png".to_string(),
+            size: 1,
+            url: format!("https://downloads.fanbox.cc/files/post/1/{}.png", id),
+        }
+    }
+
+    fn insert_post(conn: &Connection, source: &Post) -> (AuthorId, PostId) {
+        let author = AuthorId::new(1);
+        let post = conn
+            .query_row(
+                "INSERT INTO posts (author,source,title,content) VALUES (?,?,?,'[]') RETURNING id",
+                params![
+                    author,
+                    get_source_link(&source.creator_id, &source.id),
+                    source.title
+                ],
+                |row| row.get(0),
+            )
+            .unwrap();
+        (author, post)
+    }
+
+    #[test]
+    fn colliding_names_get_their_own_file_meta() {
+        let mut conn = open_archive();
+        let source = post(
+            "1",
+            PostBody {
+                files: Some(vec![attachment("a", "image"), attachment("b", "image")]),
+                ..Default::default()
+            },
+        );
+        let (author, post) = insert_post(&conn, &source);
+        // archived before names were numbered, both under the same name
+        conn.execute(
+            "INSERT INTO file_metas (filename,author,post,mime) VALUES ('image.png',?1,?2,'image/png'), ('image.png',?1,?2,'image/png')",
+            params![author, post],
+        )
+        .unwrap();
+
+        let config = Config::default();
+        let mut tx = conn.transaction().unwrap();
+        let (files, _) = sync_files(&mut tx, &config, &source, author, post).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_ne!(files[0].id, files[1].id);
+        assert_ne!(files[0].path, files[1].path);
+
+        // a second pass keeps the same file metas
+        let (again, _) = sync_files(&mut tx, &config, &source, author, post).unwrap();
+        let ids: Vec<_> = again.iter().map(|file| file.id).collect();
+        assert_eq!(ids, files.iter().map(|file| file.id).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn image_listed_twice_is_a_duplicate() {
+        let mut conn = open_archive();
+        let image = crate::fanbox::PostImage {
+            id: "i1".to_string(),
+            extension: "png".to_string(),
+            width: 1,
+            height: 1,
+            original_url: "https://downloads.fanbox.cc/images/post/1/i1.png".to_string(),
+            thumbnail_url: String::new(),
+        };
+        let source = post(
+            "1",
+            PostBody {
+                images: Some(vec![image.clone()]),
+                image_map: Some([("i1".to_string(), image)].into()),
+                ..Default::default()
+            },
+        );
+        let (author, post) = insert_post(&conn, &source);
+
+        let mut tx = conn.transaction().unwrap();
+        let (files, skipped) =
+            sync_files(&mut tx, &Config::default(), &source, author, post).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].reason, SkipReason::Duplicate(files[0].id));
+
+        let count: usize = tx
+            .query_row("SELECT COUNT(*) FROM file_metas", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn share_image_is_archived() {
+        let mut conn = open_archive();
+        let mut source = post("1", PostBody::default());
+        source.image_for_share =
+            "https://pixiv.pximg.net/c/1200x630_90_a2_g5/fanbox/public/images/post/1/cover/abc.jpeg"
+                .to_string();
+        let (author, post) = insert_post(&conn, &source);
+
+        let mut tx = conn.transaction().unwrap();
+        let (files, _) = sync_files(&mut tx, &Config::default(), &source, author, post).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].raw_id, body::SHARE_IMAGE_ID);
+        assert_eq!(files[0].path, PathBuf::from("1/1/share.jpeg"));
+    }
+
+    #[test]
+    fn failed_download_leaves_a_placeholder() {
+        let mut conn = open_archive();
+        let source = post(
+            "1",
+            PostBody {
+                files: Some(vec![attachment("a", "a"), attachment("b", "b")]),
+                ..Default::default()
+            },
+        );
+        let (author, post) = insert_post(&conn, &source);
+        let tx = conn.transaction().unwrap();
+        let ids: Vec<FileMetaId> = ["a.png", "b.png"]
+            .into_iter()
+            .map(|filename| {
+                tx.query_row(
+                    "INSERT INTO file_metas (filename,author,post,mime) VALUES (?,?,?,'image/png') RETURNING id",
+                    params![filename, author, post],
+                    |row| row.get(0),
+                )
+                .unwrap()
+            })
+            .collect();
+        let content =
+            serde_json::to_string(&[Content::File(ids[0]), Content::File(ids[1])]).unwrap();
+        tx.execute("UPDATE posts SET content = ?", [content])
+            .unwrap();
+
+        let placeholder = missing_placeholder("b.png", "https://downloads.fanbox.cc/b.png");
+        replace_files(&tx, &[(post, ids[1], placeholder)]).unwrap();
+        let content: String = tx
+            .query_row("SELECT content FROM posts", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(
+            content,
+            format!(
+                r#"[{},"[missing: b.png](https://downloads.fanbox.cc/b.png)"]"#,
+                ids[0]
+            )
+        );
+        let files: usize = tx
+            .query_row("SELECT COUNT(*) FROM file_metas", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(files, 1);
+    }
+
+    #[test]
+    fn aborted_import_is_undone() {
+        let mut conn = open_archive();
+        let (author, new) = insert_post(&conn, &post("1", PostBody::default()));
+        let (_, archived) = insert_post(&conn, &post("2", PostBody::default()));
+        let tx = conn.transaction().unwrap();
+        tx.execute(
+            "INSERT INTO file_metas (filename,author,post,mime) VALUES ('a.png',?,?,'image/png')",
+            params![author, new],
+        )
+        .unwrap();
+        let previous = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+
+        abort_post(&tx, new, None).unwrap();
+        abort_post(&tx, archived, Some(previous)).unwrap();
+        let posts: Vec<(PostId, DateTime<Utc>)> = tx
+            .prepare("SELECT id, updated FROM posts")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(posts, [(archived, previous)]);
+        let files: usize = tx
+            .query_row("SELECT COUNT(*) FROM file_metas", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(files, 0);
+    }
+
+    fn item(id: &str, title: &str, updated_day: u32) -> PostListItem {
+        let datetime = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        PostListItem {
+            id: id.to_string(),
+            title: title.to_string(),
+            fee_required: 0,
+            ty: PostType::Article,
+            published_datetime: datetime,
+            updated_datetime: Utc.with_ymd_and_hms(2024, 1, updated_day, 0, 0, 0).unwrap(),
+            tags: vec![],
+            is_liked: false,
+            like_count: 0,
+            is_commenting_restricted: false,
+            comment_count: 0,
+            is_restricted: false,
+            user: User {
+                icon_url: None,
+                name: "creator".to_string(),
+                user_id: "1".to_string(),
+            },
+            creator_id: "creator".to_string(),
+            has_adult_content: false,
+            cover: None,
+            excerpt: String::new(),
+            is_pinned: false,
+        }
+    }
+
+    #[test]
+    fn post_listed_twice_is_kept_once() {
+        // the listing may return a post twice, e.g. when it was edited while paging
+        let items = dedup_posts(vec![
+            item("1", "old", 1),
+            item("2", "other", 1),
+            item("1", "edited", 2),
+            item("1", "stale", 1),
+        ]);
+        let items: Vec<_> = items
+            .iter()
+            .map(|item| (item.id.as_str(), item.title.as_str()))
+            .collect();
+        assert_eq!(items, [("1", "edited"), ("2", "other")]);
+    }
+
+    /// Sync posts of their creator into a fresh archive, returning the connection
+    async fn sync(config: &Config, posts: Vec<Post>) -> (Connection, SyncedCreator) {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(post_archiver::utils::TEMPLATE_DATABASE_UP_SQL)
+            .unwrap();
+        let creator = Creator::from(&posts[0]);
+        let creator = crate::creator::sync_creators(&mut conn, vec![creator])
+            .unwrap()
+            .remove(0);
+        sync_again(&mut conn, config, &creator, posts).await;
+        (conn, creator)
+    }
+
+    async fn sync_again(
+        conn: &mut Connection,
+        config: &Config,
+        creator: &SyncedCreator,
+        posts: Vec<Post>,
+    ) {
+        let tags = (
+            get_or_insert_tag(conn, "fanbox").unwrap(),
+            get_or_insert_tag(conn, "free").unwrap(),
+        );
+        let mut summary = CreatorSummary::default();
+        sync_posts(conn, config, creator, posts, tags, &mut summary)
+            .await
+            .unwrap();
+        assert!(summary.failed_posts.is_empty());
+    }
+
+    fn fee_tags(conn: &Connection) -> Vec<String> {
+        let mut stmt = conn
+            .prepare("SELECT name FROM tags JOIN post_tags ON tags.id = post_tags.tag WHERE name = 'free' OR name LIKE 'fee:%' ORDER BY name")
+            .unwrap();
+        stmt.query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn free_post_is_tagged_free() {
+        let (conn, _) = sync(&Config::default(), vec![post("1", PostBody::default())]).await;
+        assert_eq!(fee_tags(&conn), ["free"]);
+    }
+
+    #[tokio::test]
+    async fn paid_post_is_tagged_with_its_fee() {
+        let mut source = post("1", PostBody::default());
+        source.fee_required = 500;
+        let (conn, _) = sync(&Config::default(), vec![source]).await;
+        assert_eq!(fee_tags(&conn), ["fee:500"]);
+    }
+
+    #[tokio::test]
+    async fn fee_change_replaces_the_fee_tag() {
+        let config = Config::default();
+        let mut source = post("1", PostBody::default());
+        source.fee_required = 500;
+        let (mut conn, creator) = sync(&config, vec![source.clone()]).await;
+
+        source.fee_required = 1000;
+        source.updated_datetime += chrono::Duration::days(1);
+        sync_again(&mut conn, &config, &creator, vec![source]).await;
+        assert_eq!(fee_tags(&conn), ["fee:1000"]);
+    }
+
+    #[tokio::test]
+    async fn counts_are_refreshed_on_re_import() {
+        let config = Config::default();
+        let mut source = post("1", PostBody::default());
+        source.like_count = 3;
+        source.comment_count = 1;
+        source.excerpt = "teaser".to_string();
+        let (mut conn, creator) = sync(&config, vec![source.clone()]).await;
+
+        source.like_count = 5;
+        source.updated_datetime += chrono::Duration::days(1);
+        sync_again(&mut conn, &config, &creator, vec![source]).await;
+        let tags: Vec<String> = conn
+            .prepare("SELECT name FROM tags JOIN post_tags ON tags.id = post_tags.tag WHERE name LIKE 'likes:%' OR name LIKE 'comments:%' ORDER BY name")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(tags, ["comments:1", "likes:5"]);
+
+        let content: String = conn
+            .query_row("SELECT content FROM posts", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(content, r#"["> teaser"]"#);
+    }
+
+    #[tokio::test]
+    async fn revision_is_written_after_commit() {
+        let _env = crate::config::ENV_LOCK.lock().await;
+        let output =
+            std::env::temp_dir().join(format!("fanbox-archive-revision-{}", std::process::id()));
+        let config = Config::try_parse_from([
+            "fanbox-archive".as_ref(),
+            "session".as_ref(),
+            output.as_os_str(),
+            "--keep-revisions".as_ref(),
+        ])
+        .unwrap();
+        let mut source = post("1", PostBody::default());
+        let (mut conn, creator) = sync(&config, vec![source.clone()]).await;
+
+        source.title = "Edited".to_string();
+        source.updated_datetime += chrono::Duration::days(1);
+        sync_again(&mut conn, &config, &creator, vec![source]).await;
+        let revision = output.join("1/1/revisions/2024-01-01T00-00-00Z.json");
+        let revision = std::fs::read_to_string(revision);
+        std::fs::remove_dir_all(&output).ok();
+        let revision: serde_json::Value = serde_json::from_str(&revision.unwrap()).unwrap();
+        assert_eq!(revision["title"], "Post 1");
+    }
+
+    #[tokio::test]
+    async fn second_sync_changes_nothing() {
+        // the config and the http client read the environment
+        let _env = crate::config::ENV_LOCK.lock().await;
+        let output =
+            std::env::temp_dir().join(format!("fanbox-archive-sync-{}", std::process::id()));
+        let config = Config::try_parse_from([
+            "fanbox-archive".as_ref(),
+            "session".as_ref(),
+            output.as_os_str(),
+        ])
+        .unwrap();
+        let image = crate::fanbox::PostImage {
+            id: "i1".to_string(),
+            extension: "png".to_string(),
+            width: 1,
+            height: 1,
+            original_url: "https://downloads.fanbox.cc/images/post/2/i1.png".to_string(),
+            thumbnail_url: String::new(),
+        };
+        let mut article = post(
+            "2",
+            PostBody {
+                blocks: Some(vec![
+                    crate::fanbox::PostBlock::P {
+                        text: "hello".to_string(),
+                        styles: None,
+                        links: None,
+                    },
+                    crate::fanbox::PostBlock::Image {
+                        image_id: "i1".to_string(),
+                    },
+                ]),
+                image_map: Some([("i1".to_string(), image)].into()),
+                ..Default::default()
+            },
+        );
+        article.ty = PostType::Article;
+        article.fee_required = 500;
+        let posts = vec![
+            post(
+                "1",
+                PostBody {
+                    text: Some("files".to_string()),
+                    files: Some(vec![attachment("a", "a")]),
+                    ..Default::default()
+                },
+            ),
+            article,
+            post(
+                "3",
+                PostBody {
+                    text: Some("text only".to_string()),
+                    ..Default::default()
+                },
+            ),
+        ];
+        // already downloaded, so nothing is fetched
+        for (post, filename) in [("1", "a.png"), ("2", "i1.png")] {
+            let folder = output.join("1").join(post);
+            std::fs::create_dir_all(&folder).unwrap();
+            std::fs::write(folder.join(filename), [0]).unwrap();
+        }
+
+        let (mut conn, creator) = sync(&config, posts.clone()).await;
+        let files: usize = conn
+            .query_row("SELECT COUNT(*) FROM file_metas", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(files, 2);
+
+        let changes = conn.total_changes();
+        sync_again(&mut conn, &config, &creator, posts).await;
+        std::fs::remove_dir_all(&output).ok();
+        assert_eq!(conn.total_changes(), changes);
+    }
 }
@@ -0,0 +1,88 @@
+use futures::future::join_all;
+use log::debug;
+use reqwest::Url;
+
+use crate::{
+    api::fanbox::FanboxClient,
+    fanbox::{LinkPreview, Post, PostTextEmbed},
+};
+
+use super::html::Fragment;
+
+/// Fetch the previews of the url embeds of posts, see `--fetch-link-previews`
+///
+/// A page which can't be fetched keeps its plain link.
+pub async fn fetch_link_previews(client: &FanboxClient, posts: &mut [Post]) {
+    let mut tasks = vec![];
+    for (index, post) in posts.iter().enumerate() {
+        let embeds = post.body.url_embed_map.iter().flatten();
+        for (id, embed) in embeds {
+            let Some(url) = embed_url(embed) else {
+                continue;
+            };
+            let client = client.clone();
+            let id = id.clone();
+            tasks.push(async move { (index, id, fetch_link_preview(&client, &url).await) });
+        }
+    }
+
+    let mut fetched = 0;
+    for (index, id, preview) in join_all(tasks).await {
+        if let Some(preview) = preview {
+            posts[index].body.link_previews.insert(id, preview);
+            fetched += 1;
+        }
+    }
+    if fetched != 0 {
+        debug!("{} link previews fetched", fetched);
+    }
+}
+
+/// Get the page of an url embed, fanbox posts and creators already have a title
+fn embed_url(embed: &PostTextEmbed) -> Option<String> {
+    match embed {
+        PostTextEmbed::Default { url, .. } => Some(url.clone()),
+        PostTextEmbed::HtmlCard { html, .. } => {
+            let fragment = Fragment::parse(html);
+            fragment
+                .find("a", "href")
+                .or_else(|| fragment.find("iframe", "src"))
+                .map(str::to_string)
+        }
+        _ => None,
+    }
+    .filter(|url| url.starts_with("https://") || url.starts_with("http://"))
+}
+
+async fn fetch_link_preview(client: &FanboxClient, url: &str) -> Option<LinkPreview> {
+    let (page, html) = match client.fetch_page(url).await {
+        Ok(page) => page,
+        Err(e) => {
+            debug!("No link preview for {}: {}", url, e);
+            return None;
+        }
+    };
+
+    let fragment = Fragment::parse(&html);
+    let title = fragment
+        .meta("og:title")
+        .map(str::to_string)
+        .or(fragment.title.clone())
+        .filter(|title| !title.is_empty())?;
+    let description = fragment
+        .meta("og:description")
+        .or(fragment.meta("description"))
+        .map(|description| description.split_whitespace().collect::<Vec<_>>().join(" "));
+    // images may be relative to the page
+    let image = fragment.meta("og:image").and_then(|image| {
+        let image = Url::parse(&page).ok()?.join(image.trim()).ok()?;
+        matches!(image.scheme(), "http" | "https").then(|| image.to_string())
+    });
+
+    Some(LinkPreview {
+        url: url.to_string(),
+        title,
+        description,
+        image,
+    })
+}
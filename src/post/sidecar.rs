@@ -0,0 +1,107 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::{config::sidecar_type::SidecarType, fanbox::Post};
+
+use super::get_source_link;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct FileSidecar {
+    pub source: String,
+    pub title: String,
+    pub creator: String,
+    pub tags: Vec<String>,
+    pub published: DateTime<Utc>,
+    pub url: String,
+}
+
+impl FileSidecar {
+    pub fn new(post: &Post, url: String) -> Self {
+        Self {
+            source: get_source_link(&post.creator_id, &post.id),
+            title: post.title(),
+            creator: post.creator(),
+            tags: post.tags.clone(),
+            published: post.published(),
+            url,
+        }
+    }
+
+    /// Get the sidecar path of a file
+    /// example: `author/post/filename.png.json`
+    pub fn path(file: &Path, ty: SidecarType) -> PathBuf {
+        let mut path = file.as_os_str().to_owned();
+        path.push(".");
+        path.push(ty.extension());
+        PathBuf::from(path)
+    }
+
+    pub fn render(&self, ty: SidecarType) -> String {
+        match ty {
+            SidecarType::Json => serde_json::to_string_pretty(self).unwrap(),
+            SidecarType::Xmp => self.to_xmp(),
+        }
+    }
+
+    fn to_xmp(&self) -> String {
+        let tags: String = self
+            .tags
+            .iter()
+            .map(|tag| format!("     <rdf:li>{}</rdf:li>\n", escape_xml(tag)))
+            .collect();
+
+        format!(
+            r#"<?xpacket begin="" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:dc="http://purl.org/dc/elements/1.1/"
+    xmlns:xmp="http://ns.adobe.com/xap/1.0/">
+   <dc:title>
+    <rdf:Alt>
+     <rdf:li xml:lang="x-default">{}</rdf:li>
+    </rdf:Alt>
+   </dc:title>
+   <dc:creator>
+    <rdf:Seq>
+     <rdf:li>{}</rdf:li>
+    </rdf:Seq>
+   </dc:creator>
+   <dc:subject>
+    <rdf:Bag>
+{}    </rdf:Bag>
+   </dc:subject>
+   <dc:source>{}</dc:source>
+   <dc:identifier>{}</dc:identifier>
+   <xmp:CreateDate>{}</xmp:CreateDate>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end="w"?>
+"#,
+            escape_xml(&self.title),
+            escape_xml(&self.creator),
+            tags,
+            escape_xml(&self.source),
+            escape_xml(&self.url),
+            self.published.to_rfc3339(),
+        )
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    for char in text.chars() {
+        match char {
+            '&' => output += "&amp;",
+            '<' => output += "&lt;",
+            '>' => output += "&gt;",
+            '"' => output += "&quot;",
+            '\'' => output += "&apos;",
+            _ => output.push(char),
+        }
+    }
+    output
+}
@@ -0,0 +1,144 @@
+use std::f64::consts::PI;
+
+use futures::future::join_all;
+use log::{debug, warn};
+use serde::Deserialize;
+
+use crate::{
+    api::fanbox::FanboxClient,
+    fanbox::{Post, Tweet},
+};
+
+/// Fetch the embedded tweets of posts, see `--fetch-tweets`
+///
+/// A tweet which can't be fetched keeps its plain link.
+pub async fn fetch_tweets(client: &FanboxClient, posts: &mut [Post]) {
+    let mut tasks = vec![];
+    for (index, post) in posts.iter().enumerate() {
+        let embeds = post.body.embed_map.iter().flatten();
+        for (id, embed) in embeds.filter(|(_, embed)| embed.service_provider == "twitter") {
+            let Some(status) = status_id(&embed.content_id) else {
+                continue;
+            };
+            let client = client.clone();
+            let id = id.clone();
+            tasks.push(async move { (index, id, fetch_tweet(&client, status).await) });
+        }
+    }
+
+    for (index, id, tweet) in join_all(tasks).await {
+        match tweet {
+            Ok(tweet) => {
+                posts[index].body.tweets.insert(id, tweet);
+            }
+            Err(e) => warn!("Failed to fetch tweet of embed {}: {}", id, e),
+        }
+    }
+}
+
+/// Get the status id of a twitter embed
+/// example: `1234` or `user/status/1234` -> `1234`
+fn status_id(content_id: &str) -> Option<u64> {
+    content_id.rsplit('/').next()?.parse().ok()
+}
+
+async fn fetch_tweet(
+    client: &FanboxClient,
+    status: u64,
+) -> Result<Option<Tweet>, Box<dyn std::error::Error + Send + Sync>> {
+    let url = format!(
+        "https://cdn.syndication.twimg.com/tweet-result?id={}&token={}",
+        status,
+        syndication_token(status)
+    );
+    let Some(tweet) = client.fetch_json::<SyndicationTweet>(&url).await? else {
+        debug!("Tweet {} is deleted", status);
+        return Ok(None);
+    };
+    // deleted or protected tweets are answered with a tombstone
+    let (Some(text), Some(user)) = (tweet.text, tweet.user) else {
+        debug!("Tweet {} is not available", status);
+        return Ok(None);
+    };
+
+    let media = tweet
+        .media_details
+        .into_iter()
+        .filter_map(|media| match media.video_info {
+            Some(video) => video
+                .variants
+                .into_iter()
+                .filter(|variant| variant.content_type == "video/mp4")
+                .max_by_key(|variant| variant.bitrate.unwrap_or_default())
+                .map(|variant| variant.url),
+            None => Some(format!("{}?name=orig", media.media_url_https)),
+        })
+        .collect();
+
+    Ok(Some(Tweet {
+        url: format!("https://twitter.com/{}/status/{}", user.screen_name, status),
+        name: user.name,
+        screen_name: user.screen_name,
+        text,
+        media,
+    }))
+}
+
+/// The token the embed widget derives from the status id
+fn syndication_token(status: u64) -> String {
+    const DIGITS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+    let value = status as f64 / 1e15 * PI;
+
+    let mut integer = value.trunc() as u64;
+    let mut token = vec![];
+    loop {
+        token.push(DIGITS[(integer % 36) as usize]);
+        integer /= 36;
+        if integer == 0 {
+            break;
+        }
+    }
+    token.reverse();
+
+    let mut fraction = value.fract();
+    for _ in 0..10 {
+        fraction *= 36.0;
+        token.push(DIGITS[fraction.trunc() as usize]);
+        fraction = fraction.fract();
+    }
+
+    token.retain(|digit| *digit != b'0');
+    String::from_utf8(token).unwrap_or_default()
+}
+
+#[derive(Deserialize, Debug)]
+struct SyndicationTweet {
+    text: Option<String>,
+    user: Option<SyndicationUser>,
+    #[serde(default, rename = "mediaDetails")]
+    media_details: Vec<SyndicationMedia>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SyndicationUser {
+    name: String,
+    screen_name: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct SyndicationMedia {
+    media_url_https: String,
+    video_info: Option<SyndicationVideo>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SyndicationVideo {
+    variants: Vec<SyndicationVariant>,
+}
+
+#[derive(Deserialize, Debug)]
+struct SyndicationVariant {
+    content_type: String,
+    url: String,
+    bitrate: Option<u64>,
+}
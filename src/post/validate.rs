@@ -0,0 +1,45 @@
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use image::{ImageFormat, ImageReader};
+
+/// Bytes at the end of a file searched for the end marker, some encoders pad after it
+const TRAILER_WINDOW: u64 = 1024;
+
+/// Check that a downloaded image is complete, returning its real dimensions
+///
+/// Only the header is decoded, JPEG and PNG are also checked for their end marker.
+/// Files which are not a supported image format return `None`.
+pub fn validate_image(path: &Path) -> Result<Option<(u32, u32)>, String> {
+    let Ok(format) = ImageFormat::from_path(path) else {
+        return Ok(None);
+    };
+    if !format.reading_enabled() {
+        return Ok(None);
+    }
+
+    let dimensions = ImageReader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .map_err(|e| e.to_string())?
+        .into_dimensions()
+        .map_err(|e| e.to_string())?;
+
+    let marker: &[u8] = match format {
+        ImageFormat::Jpeg => &[0xFF, 0xD9],
+        ImageFormat::Png => b"IEND",
+        _ => return Ok(Some(dimensions)),
+    };
+    let mut file = File::open(path).map_err(|e| e.to_string())?;
+    let size = file.metadata().map_err(|e| e.to_string())?.len();
+    file.seek(SeekFrom::Start(size.saturating_sub(TRAILER_WINDOW)))
+        .map_err(|e| e.to_string())?;
+    let mut trailer = vec![];
+    file.read_to_end(&mut trailer).map_err(|e| e.to_string())?;
+    if !trailer.windows(marker.len()).any(|window| window == marker) {
+        return Err("the image is truncated".to_string());
+    }
+    Ok(Some(dimensions))
+}
@@ -0,0 +1,39 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use log::warn;
+use tokio::sync::Notify;
+
+/// Exit code after a Ctrl+C stop, the conventional `128 + SIGINT`
+pub const EXIT_CODE: i32 = 130;
+
+static STOPPING: AtomicBool = AtomicBool::new(false);
+static NOTIFY: Notify = Notify::const_new();
+
+/// Listen for Ctrl+C, the first one stops after the posts in flight and the second one quits
+pub fn listen() {
+    tokio::spawn(async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            return;
+        }
+        warn!("Stopping after the posts in flight, press Ctrl+C again to quit now");
+        STOPPING.store(true, Ordering::SeqCst);
+        NOTIFY.notify_waiters();
+
+        if tokio::signal::ctrl_c().await.is_ok() {
+            std::process::exit(EXIT_CODE);
+        }
+    });
+}
+
+pub fn stopping() -> bool {
+    STOPPING.load(Ordering::SeqCst)
+}
+
+/// Wait until a stop was requested
+pub async fn wait() {
+    let notified = NOTIFY.notified();
+    if stopping() {
+        return;
+    }
+    notified.await;
+}
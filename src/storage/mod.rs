@@ -0,0 +1,66 @@
+use std::{io, path::Path};
+
+use log::{info, warn};
+
+use crate::{config::Config, summary::format_bytes};
+
+/// Prefix of the unfinished downloads written into the temp directory
+pub const PART_PREFIX: &str = "fanbox-archive-";
+
+/// Prepare the temp directory and warn when the disks are almost full
+///
+/// Must be called while holding the output lock. Leftovers of crashed runs are removed
+/// only when the temp directory is inside the output, as other runs may share it otherwise.
+pub fn prepare(config: &Config) -> io::Result<()> {
+    let tmp_dir = config.tmp_dir();
+    if tmp_dir.exists() && is_inside(&tmp_dir, config.output()) {
+        let mut removed = 0;
+        for entry in std::fs::read_dir(&tmp_dir)? {
+            let path = entry?.path();
+            let ours = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(PART_PREFIX) && name.ends_with(".part"));
+            if ours {
+                std::fs::remove_file(path)?;
+                removed += 1;
+            }
+        }
+        if removed != 0 {
+            info!("Removed {} leftover temp files", removed);
+        }
+    }
+    std::fs::create_dir_all(&tmp_dir)?;
+
+    check_free_space(config.output(), config.min_free_space());
+    if !same_path(&tmp_dir, config.output()) {
+        check_free_space(&tmp_dir, config.min_free_space());
+    }
+    Ok(())
+}
+
+fn check_free_space(path: &Path, min_free_space: u64) {
+    match fs4::available_space(path) {
+        Ok(available) if available < min_free_space => warn!(
+            "Only {} free in {}, downloads may fail",
+            format_bytes(available),
+            path.display()
+        ),
+        Ok(_) => {}
+        Err(e) => warn!("Failed to check free space of {}: {}", path.display(), e),
+    }
+}
+
+fn same_path(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn is_inside(path: &Path, parent: &Path) -> bool {
+    match (path.canonicalize(), parent.canonicalize()) {
+        (Ok(path), Ok(parent)) => path.starts_with(parent),
+        _ => false,
+    }
+}
@@ -0,0 +1,215 @@
+use std::{path::Path, time::Duration};
+
+use log::info;
+use serde::{Serialize, Serializer};
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CreatorSummary {
+    pub creator_id: String,
+    pub posts: usize,
+    pub skipped_posts: usize,
+    pub files: usize,
+    pub bytes: u64,
+    pub skipped_files: usize,
+    /// Source links of the posts which failed to sync
+    pub failed_posts: Vec<String>,
+    pub failed_files: usize,
+    /// Images converted by `--convert-images` and the bytes it saved
+    pub converted_files: usize,
+    pub converted_saved: u64,
+    pub stages: StageTimings,
+    #[serde(serialize_with = "serialize_secs")]
+    pub elapsed: Duration,
+}
+
+/// Time spent in each stage of archiving a creator
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct StageTimings {
+    /// Listing the posts of the creator
+    #[serde(serialize_with = "serialize_secs")]
+    pub list: Duration,
+    /// Fetching the post details
+    #[serde(serialize_with = "serialize_secs")]
+    pub fetch: Duration,
+    /// Writing posts and files into the database
+    #[serde(serialize_with = "serialize_secs")]
+    pub sync: Duration,
+    /// Downloading files
+    #[serde(serialize_with = "serialize_secs")]
+    pub download: Duration,
+}
+
+impl std::ops::AddAssign for StageTimings {
+    fn add_assign(&mut self, other: Self) {
+        self.list += other.list;
+        self.fetch += other.fetch;
+        self.sync += other.sync;
+        self.download += other.download;
+    }
+}
+
+impl CreatorSummary {
+    pub fn new(creator_id: &str) -> Self {
+        Self {
+            creator_id: creator_id.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Outcome of a whole run, used for the exit code
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RunReport {
+    pub creators: Vec<CreatorSummary>,
+    pub failed_creators: Vec<String>,
+    /// Posts given by `--post` which failed to load
+    pub failed_posts: Vec<String>,
+    #[serde(serialize_with = "serialize_secs")]
+    pub elapsed: Duration,
+}
+
+impl RunReport {
+    /// Get the failed posts of the run and of every creator
+    pub fn failed_posts(&self) -> Vec<&String> {
+        self.failed_posts
+            .iter()
+            .chain(self.creators.iter().flat_map(|c| c.failed_posts.iter()))
+            .collect()
+    }
+
+    /// Count every failed creator, post and file
+    pub fn failures(&self) -> usize {
+        let failed_files: usize = self.creators.iter().map(|c| c.failed_files).sum();
+        self.failed_creators.len() + self.failed_posts().len() + failed_files
+    }
+
+    /// Write the report as JSON
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// `0` for a clean run and `2` when something failed
+    pub fn exit_code(&self) -> i32 {
+        if self.failures() == 0 {
+            0
+        } else {
+            2
+        }
+    }
+}
+
+pub fn display_creator_summaries(summaries: &[CreatorSummary]) {
+    if summaries.is_empty() || !log::log_enabled!(log::Level::Info) {
+        return;
+    }
+
+    let mut summaries = summaries.to_vec();
+    summaries.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(b.elapsed.cmp(&a.elapsed)));
+
+    let mut id_width = 11_usize;
+    for summary in summaries.iter() {
+        id_width = summary.creator_id.len().max(id_width);
+    }
+
+    info!("Top creators by bytes:");
+    info!(
+        "+-{:-<id_width$}-+-{:-<10}-+-{:-<7}-+-{:-<7}-+-{:-<9}-+",
+        " CreatorId ", " Bytes ", " Files ", " Posts ", " Time "
+    );
+    for summary in summaries.iter() {
+        info!(
+            "| {:id_width$} | {:>10} | {:>7} | {:>7} | {:>8.1}s |",
+            summary.creator_id,
+            format_bytes(summary.bytes),
+            summary.files,
+            summary.posts,
+            summary.elapsed.as_secs_f64()
+        );
+    }
+    info!(
+        "+-{}-+------------+---------+---------+-----------+",
+        "-".repeat(id_width)
+    );
+
+    let skipped_files: usize = summaries.iter().map(|s| s.skipped_files).sum();
+    if skipped_files != 0 {
+        info!("Skipped {} files above the size limit", skipped_files);
+    }
+    info!("");
+}
+
+pub fn display_run_report(report: &RunReport) {
+    let sum = |f: fn(&CreatorSummary) -> usize| report.creators.iter().map(f).sum::<usize>();
+    let bytes: u64 = report.creators.iter().map(|c| c.bytes).sum();
+    let failed_posts = report.failed_posts();
+
+    info!("Summary:");
+    info!(
+        " + {} creators ({} failed)",
+        report.creators.len() + report.failed_creators.len(),
+        report.failed_creators.len()
+    );
+    info!(
+        " + {} posts imported, {} skipped, {} failed",
+        sum(|c| c.posts),
+        sum(|c| c.skipped_posts),
+        failed_posts.len()
+    );
+    info!(
+        " + {} files downloaded ({}), {} skipped, {} failed",
+        sum(|c| c.files),
+        format_bytes(bytes),
+        sum(|c| c.skipped_files),
+        sum(|c| c.failed_files)
+    );
+    let converted_saved: u64 = report.creators.iter().map(|c| c.converted_saved).sum();
+    if converted_saved != 0 {
+        info!(
+            " + {} images converted, {} saved",
+            sum(|c| c.converted_files),
+            format_bytes(converted_saved)
+        );
+    }
+    info!(" + {:.1}s", report.elapsed.as_secs_f64());
+    let mut stages = StageTimings::default();
+    for creator in report.creators.iter() {
+        stages += creator.stages;
+    }
+    info!(
+        " + list {:.1}s, fetch {:.1}s, sync {:.1}s, download {:.1}s",
+        stages.list.as_secs_f64(),
+        stages.fetch.as_secs_f64(),
+        stages.sync.as_secs_f64(),
+        stages.download.as_secs_f64()
+    );
+    for post in failed_posts {
+        info!(" + failed: {}", post);
+    }
+    info!("");
+}
+
+/// Format bytes as a human readable size
+/// example: `8.2 GB`
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[unit])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+fn serialize_secs<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    duration.as_secs_f64().serialize(serializer)
+}
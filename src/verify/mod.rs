@@ -0,0 +1,131 @@
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use log::{info, warn};
+use post_archiver::{AuthorId, PostId};
+use rusqlite::{Connection, OpenFlags};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{config::Config, summary::format_bytes};
+
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Default, Serialize)]
+pub struct VerifyReport {
+    pub checked: usize,
+    pub bytes: u64,
+    /// Files archived before checksums were stored, only checked for existence
+    pub unhashed: usize,
+    pub missing: Vec<PathBuf>,
+    pub corrupt: Vec<PathBuf>,
+}
+
+impl VerifyReport {
+    /// 2 when any file is missing or corrupt, like a run with failures
+    pub fn exit_code(&self) -> i32 {
+        if self.missing.is_empty() && self.corrupt.is_empty() {
+            0
+        } else {
+            2
+        }
+    }
+}
+
+/// Hash the archived fanbox files again and compare them with the stored checksums
+pub fn verify(config: &Config) -> Result<VerifyReport, Box<dyn Error>> {
+    let db_path = config.output().join("post-archiver.db");
+    if !db_path.exists() {
+        return Err(format!("No archive found at {}", db_path.display()).into());
+    }
+    let conn = Connection::open_with_flags(&db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut stmt = conn.prepare(
+        "SELECT file_metas.author, file_metas.post, file_metas.filename, json_extract(file_metas.extra, '$.sha256')
+        FROM file_metas
+        JOIN post_tags ON post_tags.post = file_metas.post
+        JOIN tags ON tags.id = post_tags.tag
+        WHERE tags.name = 'fanbox'
+        ORDER BY file_metas.id",
+    )?;
+    let files = stmt
+        .query_map([], |row| {
+            let path = PathBuf::from(row.get::<_, AuthorId>(0)?.to_string())
+                .join(row.get::<_, PostId>(1)?.to_string())
+                .join(row.get::<_, String>(2)?);
+            Ok((path, row.get::<_, Option<String>>(3)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    info!("Verifying {} files", files.len());
+    let mut report = VerifyReport::default();
+    let mut last_progress = Instant::now();
+    for (index, (path, sha256)) in files.iter().enumerate() {
+        if last_progress.elapsed() >= PROGRESS_INTERVAL {
+            last_progress = Instant::now();
+            info!(" + {}/{} files", index, files.len());
+        }
+
+        let full_path = config.output().join(path);
+        report.checked += 1;
+        let Some(sha256) = sha256 else {
+            report.unhashed += 1;
+            if !full_path.exists() {
+                warn!("Missing {}", path.display());
+                report.missing.push(path.clone());
+            }
+            continue;
+        };
+        match hash_file(&full_path) {
+            Ok((bytes, actual)) => {
+                report.bytes += bytes;
+                if &actual != sha256 {
+                    warn!("Corrupt {}", path.display());
+                    report.corrupt.push(path.clone());
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                warn!("Missing {}", path.display());
+                report.missing.push(path.clone());
+            }
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e).into()),
+        }
+    }
+
+    info!("");
+    info!(
+        "Checked: {} files ({})",
+        report.checked,
+        format_bytes(report.bytes)
+    );
+    info!("Without checksum: {}", report.unhashed);
+    info!("Missing: {}", report.missing.len());
+    info!("Corrupt: {}", report.corrupt.len());
+    Ok(report)
+}
+
+/// Stream a file through sha256, returning its size and checksum
+fn hash_file(path: &Path) -> io::Result<(u64, String)> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    let mut total = 0;
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        total += read as u64;
+    }
+    let sha256 = hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    Ok((total, sha256))
+}